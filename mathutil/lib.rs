@@ -0,0 +1,89 @@
+//! Overflow checked arithmetic on block numbers, ids, balances and
+//! oracle results shared by the contracts. The helpers return `None`
+//! instead of overflowing, each contract maps it to its own error.
+#![no_std]
+
+/// Block `blocks` after `block`.
+pub fn checked_add_block(block: u64, blocks: u64) -> Option<u64> {
+    block.checked_add(blocks)
+}
+
+/// Id following `id`.
+pub fn checked_next_id(id: u64) -> Option<u64> {
+    id.checked_add(1)
+}
+
+/// Share of `fee` in basis points, `None` for more than 10_000.
+/// Divides first instead of overflowing for very large fees.
+pub fn saturating_fee_mul(fee: u128, bps: u16) -> Option<u128> {
+    if bps > 10_000 {
+        return None;
+    }
+    Some(match fee.checked_mul(bps as u128) {
+        Some(scaled) => scaled / 10_000,
+        None => fee / 10_000 * bps as u128,
+    })
+}
+
+/// Part of `amount` released linearly after `elapsed` of `period`
+/// blocks, all of it once the period is over. Divides first
+/// instead of overflowing for very large amounts.
+pub fn linear_release(amount: u128, elapsed: u64, period: u32) -> u128 {
+    if elapsed >= period as u64 {
+        return amount;
+    }
+    let (elapsed, period) = (elapsed as u128, period as u128);
+    amount / period * elapsed + amount % period * elapsed / period
+}
+
+/// Numeric result as an unsigned value, `None` for negative results.
+pub fn checked_numeric_to_u64(value: i64) -> Option<u64> {
+    if value < 0 {
+        return None;
+    }
+    Some(value as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checked_add_block_works() {
+        assert_eq!(checked_add_block(1, 10), Some(11));
+        assert_eq!(checked_add_block(u64::MAX - 1, 1), Some(u64::MAX));
+        assert_eq!(checked_add_block(u64::MAX, 1), None);
+    }
+
+    #[test]
+    fn checked_next_id_works() {
+        assert_eq!(checked_next_id(0), Some(1));
+        assert_eq!(checked_next_id(u64::MAX), None);
+    }
+
+    #[test]
+    fn saturating_fee_mul_works() {
+        assert_eq!(saturating_fee_mul(100, 0), Some(0));
+        assert_eq!(saturating_fee_mul(100, 2_500), Some(25));
+        assert_eq!(saturating_fee_mul(100, 10_000), Some(100));
+        assert_eq!(saturating_fee_mul(100, 10_001), None);
+        assert_eq!(saturating_fee_mul(u128::MAX, 10_000), Some(u128::MAX / 10_000 * 10_000));
+    }
+
+    #[test]
+    fn linear_release_works() {
+        assert_eq!(linear_release(100, 0, 0), 100);
+        assert_eq!(linear_release(100, 0, 10), 0);
+        assert_eq!(linear_release(100, 5, 10), 50);
+        assert_eq!(linear_release(100, 20, 10), 100);
+        assert_eq!(linear_release(u128::MAX, 1, 2), u128::MAX / 2);
+    }
+
+    #[test]
+    fn checked_numeric_to_u64_works() {
+        assert_eq!(checked_numeric_to_u64(0), Some(0));
+        assert_eq!(checked_numeric_to_u64(i64::MAX), Some(i64::MAX as u64));
+        assert_eq!(checked_numeric_to_u64(-1), None);
+        assert_eq!(checked_numeric_to_u64(i64::MIN), None);
+    }
+}
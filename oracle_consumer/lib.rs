@@ -4,55 +4,266 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod oracle_consumer {
+    use ink_storage::collections::HashMap;
+
+    /// Version of the request/callback interface, bumped
+    /// whenever selectors or their arguments change.
+    pub const ABI_VERSION: u32 = 5;
+
+    /// Selector the Oracle's `callback` delivers the results to,
+    /// must match the `selector` of the result handler below.
+    pub const CALLBACK_SELECTOR: [u8; 4] = [0xB1, 0x6B, 0x00, 0xB5];
+
+    /// Selector the Oracle's `callback_with_secret` delivers the results to,
+    /// must match the `selector` of `set_bitcoin_price_with_secret`.
+    pub const SECRET_CALLBACK_SELECTOR: [u8; 4] = [0x5E, 0xC2, 0xE7, 0x00];
+
+    /// Selector of `feed_numeric`, other consumers are cross-checked trough it.
+    pub const FEED_NUMERIC_SELECTOR: [u8; 4] = [0xFE, 0xED, 0x00, 0x01];
 
     /// We add the type with currently supported Oracle results
-    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
-    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(
+        Debug,
+        Clone,
+        PartialEq,
+        Eq,
+        scale::Encode,
+        scale::Decode,
+        ink_storage::traits::SpreadLayout,
+        ink_storage::traits::PackedLayout,
+    )]
+    #[cfg_attr(
+        feature = "std",
+        derive(::scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
     pub enum OracleResult {
         Numeric(i64),
         RawBytes([u8; 32]),
+        Boolean(bool),
+        Enum(u8),
+        /// The job failed off-chain, carries the error code
+        Error(u8),
     }
 
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
     #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
     pub enum Error {
         Unauthorized,
+        InvalidResult,
+        StalePrice,
+    }
+
+    #[ink(event)]
+    pub struct ResultRejected {
+        /// Numeric result outside of the accepted range
+        value: i64,
+    }
+
+    #[ink(event)]
+    pub struct OracleAdded {
+        #[ink(topic)]
+        oracle: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct OracleRemoved {
+        #[ink(topic)]
+        oracle: AccountId,
     }
 
     #[ink(storage)]
     pub struct OracleConsumer {
-        /// The smart contract of the Oracle we are inherently trusting
+        /// The smart contracts of the Oracles we are inherently trusting
         /// with providing the data feeds
-        authorized_oracle: AccountId,
-        /// This is the value we will be updating trough the oracle
-        /// It does not have to be the same size as OracleResult::Numeric
-        bitcoin_price: u64,
+        authorized_oracles: HashMap<AccountId, ()>,
+        /// Numeric results are divided by this value before being stored,
+        /// e.g. 100 to turn basis points into whole percentages
+        divisor: u64,
+        /// Last numeric result before applying the `divisor`
+        raw_bitcoin_price: i64,
+        /// Block the bitcoin price was last updated in
+        last_update_block: u64,
+        /// Value derived from the bitcoin price by `derive_`,
+        /// recomputed only when a new price is delivered
+        derived: u64,
+        /// Admin of this contract.
+        admin: AccountId,
+        /// Numeric results below this value are rejected
+        min_accepted: i64,
+        /// Numeric results above this value are rejected
+        max_accepted: i64,
+        /// Last `OracleResult::Boolean` answer
+        boolean_result: bool,
+        /// Last `OracleResult::Enum` answer
+        enum_result: u8,
+        /// Last `OracleResult::RawBytes` answer
+        raw_result: [u8; 32],
+        /// Last result of every feed, feed 0 holds the bitcoin price
+        feeds: HashMap<u32, OracleResult>,
+        /// Block every feed was last updated in
+        feed_updates: HashMap<u32, u64>,
+        /// Expected maximum number of blocks between two updates of a feed
+        heartbeat: HashMap<u32, u64>,
+        /// Maximum difference to another oracle's result in basis points
+        /// for `cross_check` to agree
+        tolerance_bps: u16,
+        /// Hash of the secret the oracle must reveal with the next result,
+        /// the zero hash disables the check
+        secret_hash: Hash,
     }
 
     impl OracleConsumer {
 
         #[ink(constructor)]
-        pub fn new(authorized_oracle: AccountId, bitcoin_price: u64) -> Self {
+        pub fn new(
+            authorized_oracle: AccountId,
+            bitcoin_price: u64,
+            admin: AccountId,
+            divisor: u64) -> Self {
             // set the oracle which will be allowed to update our bitcoin price
             // set the intial price on contract creation
-            Self { authorized_oracle, bitcoin_price }
+            // set the admin and the transform of the numeric results
+            let mut authorized_oracles = HashMap::new();
+            authorized_oracles.insert(authorized_oracle, ());
+            let mut feeds = HashMap::new();
+            feeds.insert(0, OracleResult::Numeric(bitcoin_price as i64));
+            let mut feed_updates = HashMap::new();
+            feed_updates.insert(0, Self::env().block_number());
+            Self {
+                authorized_oracles,
+                feeds,
+                feed_updates,
+                heartbeat: HashMap::new(),
+                tolerance_bps: 0,
+                divisor,
+                raw_bitcoin_price: bitcoin_price as i64,
+                last_update_block: Self::env().block_number(),
+                derived: Self::derive_(bitcoin_price),
+                admin,
+                min_accepted: i64::MIN,
+                max_accepted: i64::MAX,
+                boolean_result: false,
+                enum_result: 0,
+                raw_result: [0; 32],
+                secret_hash: Hash::default(),
+            }
         }
 
         /// This method is called from the Oracle's `callback` fn.
         /// It can be named anything (in this case `set_bitcoin_price`),
         /// however it does need a fixed selector.
         /// The selector value needs to be the same as in the Oracle contract.
+        /// The oracle passes the `request_id` the result was requested with.
         #[ink(message, selector = "0xB16B00B5")]
-        pub fn set_bitcoin_price(&mut self, result: OracleResult) -> Result<(),Error> {
+        pub fn set_bitcoin_price(&mut self, _request_id: u64, result: OracleResult) -> Result<(),Error> {
             // check if the oracle is trusted
             let oracle = self.env().caller();
-            if oracle != self.authorized_oracle {
+            if !self.authorized_oracles.contains_key(&oracle) {
+                return Err(Error::Unauthorized);
+            }
+            // deliveries have to go trough `set_bitcoin_price_with_secret`
+            if self.secret_hash != Hash::default() {
+                return Err(Error::Unauthorized);
+            }
+
+            self.set_result_(result)
+        }
+
+        /// Same as `set_bitcoin_price`, but the oracle also reveals the
+        /// `secret` committed to in `secret_hash` and commits to the next one.
+        /// Protects the results even if the oracle key leaks.
+        /// Called from the Oracle's `callback_with_secret` fn.
+        #[ink(message, selector = "0x5EC2E700")]
+        pub fn set_bitcoin_price_with_secret(
+            &mut self,
+            _request_id: u64,
+            result: OracleResult,
+            secret: [u8; 32],
+            next_secret_hash: Hash) -> Result<(),Error> {
+            let oracle = self.env().caller();
+            if !self.authorized_oracles.contains_key(&oracle) {
+                return Err(Error::Unauthorized);
+            }
+            if self.secret_hash != self.hash_secret_(&secret) {
+                return Err(Error::Unauthorized);
+            }
+
+            self.set_result_(result)?;
+            // each secret can only be used once
+            self.secret_hash = next_secret_hash;
+            Ok(())
+        }
+
+        /// This is the value we will be updating trough the oracle,
+        /// kept in feed 0. It does not have to be the same size as
+        /// OracleResult::Numeric
+        fn bitcoin_price_(&self) -> u64 {
+            self.feed_numeric(0).unwrap_or(0) as u64
+        }
+
+        /// Trust deliveries from another oracle, e.g. a backup one
+        #[ink(message)]
+        pub fn add_oracle(&mut self, oracle: AccountId) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            self.authorized_oracles.insert(oracle, ());
+            self.env().emit_event(OracleAdded { oracle });
+            Ok(())
+        }
+
+        /// Stop trusting deliveries from the oracle
+        #[ink(message)]
+        pub fn remove_oracle(&mut self, oracle: AccountId) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            self.authorized_oracles.take(&oracle);
+            self.env().emit_event(OracleRemoved { oracle });
+            Ok(())
+        }
+
+        /// Change the hash of the secret the oracle has to reveal,
+        /// the zero hash turns the check off.
+        #[ink(message)]
+        pub fn set_secret_hash(&mut self, secret_hash: Hash) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
                 return Err(Error::Unauthorized);
             }
+            self.secret_hash = secret_hash;
+            Ok(())
+        }
 
+        fn hash_secret_(&self, secret: &[u8; 32]) -> Hash {
+            Hash::from(self.env().hash_bytes::<ink_env::hash::Blake2x256>(secret))
+        }
+
+        fn set_result_(&mut self, result: OracleResult) -> Result<(),Error> {
             // set the oracle's value
-            if let OracleResult::Numeric(price) = result {
-                self.bitcoin_price = price as u64;
+            match result {
+                OracleResult::Numeric(price) => {
+                    if self.divisor == 0 {
+                        return Err(Error::InvalidResult);
+                    }
+                    // keep the previous price if the oracle misbehaves
+                    if price < self.min_accepted || price > self.max_accepted {
+                        self.env().emit_event(ResultRejected { value: price });
+                        return Err(Error::InvalidResult);
+                    }
+                    let bitcoin_price = mathutil::checked_numeric_to_u64(price)
+                        .ok_or(Error::InvalidResult)?
+                        / self.divisor;
+                    self.raw_bitcoin_price = price;
+                    self.feeds.insert(0, OracleResult::Numeric(bitcoin_price as i64));
+                    self.last_update_block = self.env().block_number();
+                    self.feed_updates.insert(0, self.last_update_block);
+                    self.derived = Self::derive_(bitcoin_price);
+                }
+                OracleResult::Boolean(value) => self.boolean_result = value,
+                OracleResult::Enum(value) => self.enum_result = value,
+                OracleResult::RawBytes(value) => self.raw_result = value,
+                // the job failed, keep the previous values
+                OracleResult::Error(_) => (),
             }
 
             // Let the oracle know all is good
@@ -64,20 +275,575 @@ mod oracle_consumer {
         /// by using large ints.
         #[ink(message)]
         pub fn its_over_9000(&self) -> bool {
-            self.bitcoin_price > 9000 as u64 * 1e8 as u64
+            self.is_over(9000 * 1e8 as u64)
+        }
+
+        /// Whether the bitcoin price is above `threshold_scaled`, using
+        /// the same 8 decimal points of precision as the price itself.
+        #[ink(message)]
+        pub fn is_over(&self, threshold_scaled: u64) -> bool {
+            self.bitcoin_price_() > threshold_scaled
+        }
+
+        /// Whether the bitcoin price is below `threshold_scaled`.
+        #[ink(message)]
+        pub fn is_under(&self, threshold_scaled: u64) -> bool {
+            self.bitcoin_price_() < threshold_scaled
+        }
+
+        /// Same as `its_over_9000`, but refuses to answer with a price
+        /// older than `max_age` blocks.
+        #[ink(message)]
+        pub fn its_over_9000_fresh(&self, max_age: u64) -> Result<bool,Error> {
+            if self.env().block_number().saturating_sub(self.last_update_block) > max_age {
+                return Err(Error::StalePrice);
+            }
+            Ok(self.its_over_9000())
+        }
+
+        /// Store the result of a feed as delivered.
+        /// Feed 0 is the bitcoin price, its results are handled
+        /// the same as the ones delivered to `set_bitcoin_price`.
+        #[ink(message)]
+        pub fn set_feed(&mut self, feed_id: u32, result: OracleResult) -> Result<(),Error> {
+            let oracle = self.env().caller();
+            if !self.authorized_oracles.contains_key(&oracle) {
+                return Err(Error::Unauthorized);
+            }
+            // deliveries have to go trough `set_feed_with_secret`
+            if self.secret_hash != Hash::default() {
+                return Err(Error::Unauthorized);
+            }
+            self.set_feed_(feed_id, result)
+        }
+
+        /// Same as `set_feed`, but the oracle also reveals the `secret`
+        /// committed to in `secret_hash` and commits to the next one.
+        #[ink(message)]
+        pub fn set_feed_with_secret(
+            &mut self,
+            feed_id: u32,
+            result: OracleResult,
+            secret: [u8; 32],
+            next_secret_hash: Hash) -> Result<(),Error> {
+            let oracle = self.env().caller();
+            if !self.authorized_oracles.contains_key(&oracle) {
+                return Err(Error::Unauthorized);
+            }
+            if self.secret_hash != self.hash_secret_(&secret) {
+                return Err(Error::Unauthorized);
+            }
+
+            self.set_feed_(feed_id, result)?;
+            // each secret can only be used once
+            self.secret_hash = next_secret_hash;
+            Ok(())
+        }
+
+        fn set_feed_(&mut self, feed_id: u32, result: OracleResult) -> Result<(),Error> {
+            if feed_id == 0 {
+                return self.set_result_(result);
+            }
+            self.feeds.insert(feed_id, result);
+            self.feed_updates.insert(feed_id, self.env().block_number());
+            Ok(())
+        }
+
+        /// Set the expected maximum number of blocks between two updates
+        /// of the feed, 0 disables the heartbeat.
+        #[ink(message)]
+        pub fn set_heartbeat(&mut self, feed_id: u32, blocks: u64) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            if blocks == 0 {
+                self.heartbeat.take(&feed_id);
+            } else {
+                self.heartbeat.insert(feed_id, blocks);
+            }
+            Ok(())
+        }
+
+        /// Whether the feed went longer than its heartbeat without an update,
+        /// e.g. to switch to a fallback feed. Always `false` without a heartbeat.
+        #[ink(message)]
+        pub fn missed_heartbeat(&self, feed_id: u32) -> bool {
+            match self.heartbeat.get(&feed_id) {
+                Some(blocks) => {
+                    let updated_at = *self.feed_updates.get(&feed_id).unwrap_or(&0);
+                    self.env().block_number().saturating_sub(updated_at) > *blocks
+                }
+                None => false,
+            }
+        }
+
+        /// Last result of the feed if it was numeric
+        #[ink(message, selector = "0xFEED0001")]
+        pub fn feed_numeric(&self, feed_id: u32) -> Option<i64> {
+            match self.feeds.get(&feed_id) {
+                Some(OracleResult::Numeric(value)) => Some(*value),
+                _ => None,
+            }
+        }
+
+        /// Last result of the feed if it was raw bytes
+        #[ink(message)]
+        pub fn feed_raw(&self, feed_id: u32) -> Option<[u8; 32]> {
+            match self.feeds.get(&feed_id) {
+                Some(OracleResult::RawBytes(value)) => Some(*value),
+                _ => None,
+            }
+        }
+
+        /// Last result of the feed if it was a yes/no answer
+        #[ink(message)]
+        pub fn feed_bool(&self, feed_id: u32) -> Option<bool> {
+            match self.feeds.get(&feed_id) {
+                Some(OracleResult::Boolean(value)) => Some(*value),
+                _ => None,
+            }
+        }
+
+        /// Last numeric result as delivered, before applying the divisor
+        #[ink(message)]
+        pub fn raw_bitcoin_price(&self) -> i64 {
+            self.raw_bitcoin_price
+        }
+
+        /// Change the divisor applied to numeric results
+        #[ink(message)]
+        pub fn set_divisor(&mut self, divisor: u64) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            if divisor == 0 {
+                return Err(Error::InvalidResult);
+            }
+            self.divisor = divisor;
+            Ok(())
+        }
+
+        /// Change the range of accepted numeric results
+        #[ink(message)]
+        pub fn set_accepted_range(&mut self, min_accepted: i64, max_accepted: i64) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            if min_accepted > max_accepted {
+                return Err(Error::InvalidResult);
+            }
+            self.min_accepted = min_accepted;
+            self.max_accepted = max_accepted;
+            Ok(())
+        }
+
+        /// Last yes/no answer delivered by the oracle
+        #[ink(message)]
+        pub fn boolean_result(&self) -> bool {
+            self.boolean_result
+        }
+
+        /// Last enum answer delivered by the oracle
+        #[ink(message)]
+        pub fn enum_result(&self) -> u8 {
+            self.enum_result
+        }
+
+        /// Last raw answer delivered by the oracle
+        #[ink(message)]
+        pub fn raw_result(&self) -> [u8; 32] {
+            self.raw_result
+        }
+
+        /// First 16 bytes of the raw answer as a big-endian number
+        #[ink(message)]
+        pub fn raw_as_u128(&self) -> u128 {
+            self.raw_as_two_u128().0
+        }
+
+        /// Raw answer as two big-endian numbers
+        #[ink(message)]
+        pub fn raw_as_two_u128(&self) -> (u128, u128) {
+            let mut high = [0u8; 16];
+            let mut low = [0u8; 16];
+            high.copy_from_slice(&self.raw_result[..16]);
+            low.copy_from_slice(&self.raw_result[16..]);
+            (u128::from_be_bytes(high), u128::from_be_bytes(low))
+        }
+
+        /// Raw answer as four big-endian signed numbers
+        #[ink(message)]
+        pub fn raw_as_i64_array(&self) -> [i64; 4] {
+            let mut values = [0i64; 4];
+            for (i, chunk) in self.raw_result.chunks(8).enumerate() {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(chunk);
+                values[i] = i64::from_be_bytes(bytes);
+            }
+            values
+        }
+
+        /// Whether our numeric feed agrees within `tolerance_bps` with the one
+        /// of another consumer exposing `feed_numeric`. An unreachable
+        /// contract or a missing feed never agrees.
+        #[ink(message)]
+        pub fn cross_check(&self, other_oracle: AccountId, feed_id: u32) -> bool {
+            let ours = match self.feed_numeric(feed_id) {
+                Some(value) => value,
+                None => return false,
+            };
+
+            use ink_env::call::{build_call, utils::ReturnType, ExecutionInput, Selector};
+            let theirs = build_call::<ink_env::DefaultEnvironment>()
+                .callee(other_oracle)
+                .gas_limit(1_000_000)
+                .exec_input(ExecutionInput::new(Selector::new(FEED_NUMERIC_SELECTOR)).push_arg(feed_id))
+                .returns::<ReturnType<Option<i64>>>()
+                .fire();
+            match theirs {
+                Ok(Some(theirs)) => Self::agrees_(ours, theirs, self.tolerance_bps),
+                _ => false,
+            }
+        }
+
+        /// Change the tolerance of `cross_check`
+        #[ink(message)]
+        pub fn set_tolerance_bps(&mut self, tolerance_bps: u16) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            self.tolerance_bps = tolerance_bps;
+            Ok(())
+        }
+
+        /// Value derived from the bitcoin price, cached by `set_bitcoin_price`.
+        #[ink(message)]
+        pub fn get_derived(&self) -> u64 {
+            self.derived
+        }
+
+        /// Price of a 50/50 bitcoin/stablecoin pool share backed by one bitcoin,
+        /// both halves are worth the bitcoin price.
+        fn derive_(bitcoin_price: u64) -> u64 {
+            bitcoin_price.saturating_mul(2)
+        }
+
+        fn agrees_(ours: i64, theirs: i64, tolerance_bps: u16) -> bool {
+            let difference = (ours as i128 - theirs as i128).abs();
+            difference * 10_000 <= (ours as i128).abs() * tolerance_bps as i128
+        }
+
+        /// Returns the result as decoded, to check the encoding of a
+        /// result round-trips before delivering it for real.
+        #[ink(message)]
+        pub fn echo_result(&self, result: OracleResult) -> OracleResult {
+            result
+        }
+
+        /// Version of the oracle request/callback interface
+        /// spoken by this contract.
+        #[ink(message)]
+        pub fn abi_version(&self) -> u32 {
+            ABI_VERSION
         }
     }
 
     #[cfg(test)]
     mod tests {
         use super::*;
+        use ink_lang as ink;
+        use scale::Encode;
+
+        /// Whether the contract has a message with the `selector` taking the `args`,
+        /// decoded the way the contract dispatches the calls
+        fn dispatches(selector: [u8; 4], args: impl Encode) -> bool {
+            let mut input = selector.to_vec();
+            args.encode_to(&mut input);
+            <<OracleConsumer as ::ink_lang::MessageDispatcher>::Type as scale::Decode>::decode(
+                &mut &input[..],
+            )
+            .is_ok()
+        }
+
+        #[test]
+        fn callback_selector_matches() {
+            assert!(dispatches(CALLBACK_SELECTOR, (1u64, OracleResult::Numeric(42))));
+            assert!(dispatches(
+                SECRET_CALLBACK_SELECTOR,
+                (1u64, OracleResult::Numeric(42), [0x01u8; 32], Hash::from([0x02; 32]))
+            ));
+            assert!(!dispatches([0x00; 4], (1u64, OracleResult::Numeric(42))));
+        }
+
+        #[test]
+        fn feed_numeric_selector_matches() {
+            assert!(dispatches(FEED_NUMERIC_SELECTOR, 0u32));
+        }
 
         #[test]
+        fn agrees_within_tolerance() {
+            assert!(OracleConsumer::agrees_(10_000, 10_000, 0));
+            assert!(!OracleConsumer::agrees_(10_000, 10_001, 0));
+            // 1% either way
+            assert!(OracleConsumer::agrees_(10_000, 10_100, 100));
+            assert!(OracleConsumer::agrees_(10_000, 9_900, 100));
+            assert!(!OracleConsumer::agrees_(10_000, 10_101, 100));
+            assert!(!OracleConsumer::agrees_(-10_000, 10_000, 100));
+        }
+
+        #[ink::test]
         fn default_works() {
             let oracle_stub: AccountId = [0x0; 32].into();
-            let c = OracleConsumer::new(oracle_stub, 0);
+            let admin_stub: AccountId = [0x0; 32].into();
+            let c = OracleConsumer::new(oracle_stub, 0, admin_stub, 1);
             assert_eq!(c.its_over_9000(), false);
         }
 
+        #[ink::test]
+        fn divisor_passthrough() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 1);
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(4250)), Ok(()));
+            assert_eq!(c.bitcoin_price_(), 4250);
+            assert_eq!(c.raw_bitcoin_price(), 4250);
+        }
+
+        #[ink::test]
+        fn divisor_converts_basis_points() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 100);
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(4250)), Ok(()));
+            assert_eq!(c.bitcoin_price_(), 42);
+            assert_eq!(c.raw_bitcoin_price(), 4250);
+
+            assert_eq!(c.set_divisor(0), Err(Error::InvalidResult));
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 0);
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(4250)), Err(Error::InvalidResult));
+        }
+
+        #[ink::test]
+        fn rejects_out_of_range_results() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 1);
+            assert_eq!(c.set_accepted_range(100, 200), Ok(()));
+
+            // both bounds are inclusive
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(100)), Ok(()));
+            assert_eq!(c.bitcoin_price_(), 100);
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(200)), Ok(()));
+            assert_eq!(c.bitcoin_price_(), 200);
+
+            // previous price is kept
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(99)), Err(Error::InvalidResult));
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(201)), Err(Error::InvalidResult));
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(i64::MAX)), Err(Error::InvalidResult));
+            assert_eq!(c.bitcoin_price_(), 200);
+        }
+
+        #[ink::test]
+        fn requires_secret_once_committed() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 1);
+            let secret = [0x01; 32];
+            let next_secret = [0x02; 32];
+            assert_eq!(c.set_secret_hash(c.hash_secret_(&secret)), Ok(()));
+
+            // plain deliveries and wrong secrets are rejected
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(1)), Err(Error::Unauthorized));
+            assert_eq!(
+                c.set_bitcoin_price_with_secret(1, OracleResult::Numeric(1), next_secret, Hash::default()),
+                Err(Error::Unauthorized));
+            assert_eq!(c.bitcoin_price_(), 0);
+
+            let next_hash = c.hash_secret_(&next_secret);
+            assert_eq!(
+                c.set_bitcoin_price_with_secret(1, OracleResult::Numeric(1), secret, next_hash),
+                Ok(()));
+            assert_eq!(c.bitcoin_price_(), 1);
+
+            // the secret was rotated
+            assert_eq!(
+                c.set_bitcoin_price_with_secret(1, OracleResult::Numeric(2), secret, next_hash),
+                Err(Error::Unauthorized));
+            assert_eq!(
+                c.set_bitcoin_price_with_secret(1, OracleResult::Numeric(2), next_secret, Hash::default()),
+                Ok(()));
+            assert_eq!(c.bitcoin_price_(), 2);
+        }
+
+        #[ink::test]
+        fn accepts_any_registered_oracle() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.bob, 0, accounts.alice, 1);
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(1)), Err(Error::Unauthorized));
+
+            assert_eq!(c.add_oracle(accounts.alice), Ok(()));
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(1)), Ok(()));
+            assert_eq!(c.bitcoin_price_(), 1);
+
+            assert_eq!(c.remove_oracle(accounts.alice), Ok(()));
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(2)), Err(Error::Unauthorized));
+            assert!(c.authorized_oracles.contains_key(&accounts.bob));
+        }
+
+        #[ink::test]
+        fn decodes_raw_bytes() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 1);
+            let mut raw = [0u8; 32];
+            raw[15] = 1;
+            raw[31] = 2;
+            raw[16..24].copy_from_slice(&(-3i64).to_be_bytes());
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::RawBytes(raw)), Ok(()));
+
+            assert_eq!(c.raw_result(), raw);
+            assert_eq!(c.raw_as_u128(), 1);
+            assert_eq!(c.raw_as_two_u128().0, 1);
+            assert_eq!(c.raw_as_i64_array(), [0, 1, -3, 2]);
+        }
+
+        #[ink::test]
+        fn ignores_error_results() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 42, accounts.alice, 1);
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Error(1)), Ok(()));
+            assert_eq!(c.bitcoin_price_(), 42);
+        }
+
+        #[ink::test]
+        fn stores_feeds() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 1);
+            assert_eq!(c.set_feed(1, OracleResult::Boolean(true)), Ok(()));
+            assert_eq!(c.set_feed(2, OracleResult::RawBytes([0x42; 32])), Ok(()));
+            assert_eq!(c.feed_bool(1), Some(true));
+            assert_eq!(c.feed_numeric(1), None);
+            assert_eq!(c.feed_raw(2), Some([0x42; 32]));
+            assert_eq!(c.feed_numeric(3), None);
+
+            // the bitcoin price lives in feed 0
+            assert_eq!(c.feed_numeric(0), Some(0));
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(9001 * 1e8 as i64)), Ok(()));
+            assert_eq!(c.feed_numeric(0), Some(9001 * 1e8 as i64));
+            assert!(c.its_over_9000());
+
+            // and is written like the bitcoin price trough `set_feed`
+            assert_eq!(c.set_feed(0, OracleResult::Numeric(42)), Ok(()));
+            assert_eq!(c.bitcoin_price_(), 42);
+            assert_eq!(c.get_derived(), 84);
+            assert!(!c.its_over_9000());
+            assert_eq!(c.set_feed(0, OracleResult::Numeric(-1)), Err(Error::InvalidResult));
+            assert_eq!(c.feed_numeric(0), Some(42));
+        }
+
+        #[ink::test]
+        fn stores_feeds_with_secret() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 1);
+            let secret = [0x01; 32];
+            assert_eq!(c.set_secret_hash(c.hash_secret_(&secret)), Ok(()));
+            assert_eq!(c.set_feed(1, OracleResult::Numeric(1)), Err(Error::Unauthorized));
+            assert_eq!(
+                c.set_feed_with_secret(1, OracleResult::Numeric(1), [0x02; 32], Hash::default()),
+                Err(Error::Unauthorized));
+
+            assert_eq!(
+                c.set_feed_with_secret(1, OracleResult::Numeric(1), secret, Hash::default()),
+                Ok(()));
+            assert_eq!(c.feed_numeric(1), Some(1));
+            // the secret check is off once the zero hash is committed
+            assert_eq!(c.set_feed(1, OracleResult::Numeric(2)), Ok(()));
+        }
+
+        #[ink::test]
+        fn compares_against_threshold() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 1);
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(4250)), Ok(()));
+            assert!(c.is_over(4249));
+            assert!(!c.is_over(4250));
+            assert!(c.is_under(4251));
+            assert!(!c.is_under(4250));
+            assert!(!c.its_over_9000());
+        }
+
+        #[ink::test]
+        fn caches_derived_value() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 10, accounts.alice, 1);
+            assert_eq!(c.get_derived(), 20);
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(42)), Ok(()));
+            assert_eq!(c.get_derived(), 84);
+
+            // only new prices recompute it
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Boolean(true)), Ok(()));
+            assert_eq!(c.get_derived(), 84);
+            assert_eq!(OracleConsumer::derive_(u64::MAX), u64::MAX);
+        }
+
+        #[ink::test]
+        fn detects_missed_heartbeat() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 1);
+            assert_eq!(c.set_feed(1, OracleResult::Numeric(1)), Ok(()));
+            assert_eq!(c.set_heartbeat(1, 2), Ok(()));
+            assert!(!c.missed_heartbeat(0));
+
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert!(!c.missed_heartbeat(1));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            assert!(c.missed_heartbeat(1));
+
+            // a new update restarts the heartbeat
+            assert_eq!(c.set_feed(1, OracleResult::Numeric(2)), Ok(()));
+            assert!(!c.missed_heartbeat(1));
+            assert_eq!(c.set_heartbeat(1, 0), Ok(()));
+        }
+
+        #[ink::test]
+        fn rejects_stale_price() {
+            let accounts = default_accounts();
+            let mut c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 1);
+            assert_eq!(c.its_over_9000_fresh(0), Ok(false));
+
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(c.its_over_9000_fresh(3), Ok(false));
+            assert_eq!(c.its_over_9000_fresh(2), Err(Error::StalePrice));
+
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(1)), Ok(()));
+            assert_eq!(c.its_over_9000_fresh(0), Ok(false));
+        }
+
+        #[ink::test]
+        fn echoes_result() {
+            let accounts = default_accounts();
+            let c = OracleConsumer::new(accounts.alice, 0, accounts.alice, 1);
+            assert_eq!(c.echo_result(OracleResult::Numeric(-1)), OracleResult::Numeric(-1));
+            assert_eq!(c.echo_result(OracleResult::Error(3)), OracleResult::Error(3));
+        }
+
+        #[test]
+        fn result_encoding_is_stable() {
+            // must match the encoding of the TrustedOracle's `OracleResult`
+            assert_eq!(OracleResult::Numeric(1).encode(), vec![0, 1, 0, 0, 0, 0, 0, 0, 0]);
+            assert_eq!(OracleResult::RawBytes([0x42; 32]).encode()[0], 1);
+            assert_eq!(OracleResult::Boolean(true).encode(), vec![2, 1]);
+            assert_eq!(OracleResult::Enum(7).encode(), vec![3, 7]);
+            assert_eq!(OracleResult::Error(1).encode(), vec![4, 1]);
+        }
+
+        //
+        // helper functions
+        //
+        fn default_accounts(
+        ) -> ink_env::test::DefaultAccounts<ink_env::DefaultEnvironment> {
+            ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain environment should have been initialized already")
+        }
+
     }
 }
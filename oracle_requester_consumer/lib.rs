@@ -4,6 +4,30 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod oracle_requester_consumer {
+    use ink_storage::collections::HashMap;
+
+    /// Version of the request/callback interface, bumped
+    /// whenever selectors or their arguments change.
+    pub const ABI_VERSION: u32 = 5;
+
+    /// Selector the Oracle's `callback` delivers the results to,
+    /// must match the `selector` of the result handler below.
+    pub const CALLBACK_SELECTOR: [u8; 4] = [0xB1, 0x6B, 0x00, 0xB5];
+
+    /// Selector of the Oracle's `request_with_tip` message.
+    pub const ORACLE_REQUEST_WITH_TIP_SELECTOR: [u8; 4] = [0xB1, 0x6B, 0x00, 0xB6];
+
+    /// Selector of the Oracle's `withdraw_refund` message.
+    pub const ORACLE_WITHDRAW_REFUND_SELECTOR: [u8; 4] = [0x4E, 0xF0, 0xD0, 0x00];
+
+    /// Selector of the Oracle's `get_fee` message.
+    pub const ORACLE_FEE_SELECTOR: [u8; 4] = [0xFE, 0xE0, 0x00, 0x00];
+
+    /// Selector of the Oracle's `cancel_request` message.
+    pub const ORACLE_CANCEL_SELECTOR: [u8; 4] = [0xCA, 0x4C, 0xE1, 0x00];
+
+    /// Handler tag of the requests updating `bitcoin_price`.
+    pub const BITCOIN_PRICE_HANDLER: u32 = 0;
 
     /// We add the type with currently supported Oracle results
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
@@ -11,6 +35,10 @@ mod oracle_requester_consumer {
     pub enum OracleResult {
         Numeric(i64),
         RawBytes([u8; 32]),
+        Boolean(bool),
+        Enum(u8),
+        /// The job failed off-chain, carries the error code
+        Error(u8),
     }
 
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
@@ -18,6 +46,30 @@ mod oracle_requester_consumer {
     pub enum Error {
         Unauthorized,
         OracleRequestError,
+        ValueError,
+        FeeTooHigh,
+        AlreadyFulfilled,
+        TransferFailed,
+        InsufficientReserve,
+        RefundPending,
+    }
+
+    #[ink(event)]
+    pub struct RequestFulfilled {
+        #[ink(topic)]
+        request_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct RequesterAdded {
+        #[ink(topic)]
+        requester: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct RequesterRemoved {
+        #[ink(topic)]
+        requester: AccountId,
     }
 
     #[ink(storage)]
@@ -30,6 +82,21 @@ mod oracle_requester_consumer {
         bitcoin_price: u64,
         /// Admin of this contract.
         admin: AccountId,
+        /// Share of the `request_with_funding` payment kept in reserve, in basis points
+        reserve_bps: u16,
+        /// Funds kept in the contract for the next requests
+        reserve: Balance,
+        /// Requests paying a higher fee than this are rejected
+        max_fee: Balance,
+        /// Store <RequestId, HandlerTag> of the requests the oracle has not answered yet
+        outstanding: HashMap<u64, u32>,
+        /// Latest numeric result delivered to each of the other handlers
+        handler_results: HashMap<u32, i64>,
+        /// Store <RequestId, (pql, valid_period, priority, callback_value, tip, payment)>
+        /// of the outstanding requests, to resubmit them
+        request_params: HashMap<u64, (Hash, u32, u8, Balance, Balance, Balance)>,
+        /// Accounts besides the admin allowed to call `init_request`
+        requesters: HashMap<AccountId, ()>,
     }
 
     impl OracleRequesterConsumer {
@@ -46,6 +113,13 @@ mod oracle_requester_consumer {
                 authorized_oracle,
                 bitcoin_price,
                 admin,
+                reserve_bps: 0,
+                reserve: 0,
+                max_fee: Balance::MAX,
+                outstanding: HashMap::new(),
+                handler_results: HashMap::new(),
+                request_params: HashMap::new(),
+                requesters: HashMap::new(),
             }
         }
 
@@ -56,56 +130,238 @@ mod oracle_requester_consumer {
         ///
         /// In principle your smart contract does not need to be an originator of a request.
         /// If you need only to recieve results into your smart contract, check `OracleConsumer`.
+        ///
+        /// The result is routed to the handler identified by `handler_tag`,
+        /// `BITCOIN_PRICE_HANDLER` updates the bitcoin price.
         #[ink(message, payable)]
-        pub fn init_request(&mut self, pql: Hash, valid_period: u32) -> Result<(),Error> {
-            // only admin can request an oracle job
+        pub fn init_request(
+            &mut self,
+            pql: Hash,
+            valid_period: u32,
+            priority: u8,
+            callback_value: Balance,
+            handler_tag: u32) -> Result<(),Error> {
+            // only admin and the allowed requesters can request an oracle job
             // to avoid this requirement, you can:
             //  - pre-fund the contract with sufficent balance to pay for fees
             //  - make pql_hash and valid_period part of self.()
             let who = self.env().caller();
-            if who != self.admin {
+            if who != self.admin && !self.requesters.contains_key(&who) {
                 return Err(Error::Unauthorized);
             }
 
-            // the amount sent to this call will be forwarded to the oracle to pay the fee
+            // the amount sent to this call will be forwarded to the oracle to pay
+            // the fee and the `callback_value` it sends back along with the result
             let fee = self.env().transferred_balance();
 
-            // request data from our oracle
-            use ink_env::call::{build_call, Selector, ExecutionInput};
-            let selector = Selector::new([
-                0xB1, 0x6B, 0x00, 0xB5,
-            ]);
-            let request = build_call::<ink_env::DefaultEnvironment>()
+            self.request_(pql, valid_period, priority, callback_value, 0, fee, handler_tag)?;
+            Ok(())
+        }
+
+        /// Request the work from the Oracle and top up the reserve in one go.
+        /// `reserve_bps` of the attached value are set aside for the next
+        /// requests. The oracle fee is paid from the rest of it, any shortfall
+        /// is taken from the reserve and any leftover is added to the reserve.
+        /// Returns the request id assigned by the oracle.
+        #[ink(message, payable)]
+        pub fn request_with_funding(&mut self, pql: Hash, valid_period: u32) -> Result<u64,Error> {
+            let who = self.env().caller();
+            if who != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            let funding = self.env().transferred_balance();
+            let fee = self.oracle_fee_()?;
+            let reserve = self.fund_request_(funding, fee)?;
+            let request_id = self.request_(pql, valid_period, 0, 0, 0, fee, BITCOIN_PRICE_HANDLER)?;
+            self.reserve = reserve;
+            Ok(request_id)
+        }
+
+        /// Cancel a request the oracle is taking too long to answer, and
+        /// resubmit it with the refunded payment. The attached value is
+        /// added to the tip the oracle earns on top of its fee.
+        /// Returns the new request id, or `AlreadyFulfilled` if the
+        /// request can't be cancelled anymore. If the oracle owes the refund
+        /// instead of paying it, the request is not resubmitted and
+        /// `RefundPending` is returned, see `withdraw_oracle_refund`.
+        #[ink(message, payable)]
+        pub fn bump_request(&mut self, request_id: u64) -> Result<u64,Error> {
+            let who = self.env().caller();
+            if who != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            if !self.outstanding.contains_key(&request_id) {
+                return Err(Error::AlreadyFulfilled);
+            }
+
+            // check the resubmission before the original request is cancelled
+            let tip = self.env().transferred_balance();
+            let oracle_fee = self.oracle_fee_()?;
+            let (pql, valid_period, priority, callback_value, tip, payment, handler_tag) =
+                self.bump_params_(request_id, tip, oracle_fee)?;
+            let refund = self.request_params.get(&request_id).map(|params| params.5).unwrap_or(0);
+            let expected_balance = self.env().balance().saturating_add(refund);
+
+            // the oracle refuses to cancel delivered requests
+            use ink_env::call::{build_call, utils::ReturnType, Selector, ExecutionInput};
+            let cancel = build_call::<ink_env::DefaultEnvironment>()
                 .callee(self.authorized_oracle)
                 .gas_limit(1_000_000)
-                .transferred_value(fee)
-                .exec_input(ExecutionInput::new(selector)
-                    .push_arg(&pql)
-                    .push_arg(&valid_period))
-                .returns::<()>()
+                .transferred_value(0)
+                .exec_input(ExecutionInput::new(Selector::new(ORACLE_CANCEL_SELECTOR))
+                    .push_arg(&request_id))
+                .returns::<ReturnType<Result<(), u8>>>()
                 .fire();
-            if let Err(_) = request {
-                return Err(Error::OracleRequestError);
+            if !matches!(cancel, Ok(Ok(()))) {
+                return Err(Error::AlreadyFulfilled);
+            }
+            self.outstanding.take(&request_id);
+            self.request_params.take(&request_id);
+
+            // the oracle keeps refunds it can't transfer for us to withdraw
+            if self.env().balance() < expected_balance {
+                self.withdraw_oracle_refund_();
+            }
+            if self.env().balance() < expected_balance {
+                return Err(Error::RefundPending);
             }
+            self.request_(pql, valid_period, priority, callback_value, tip, payment, handler_tag)
+        }
 
+        /// Withdraw the refunds the oracle owes this contract,
+        /// e.g. after `bump_request` returned `RefundPending`.
+        #[ink(message)]
+        pub fn withdraw_oracle_refund(&mut self) -> Result<(),Error> {
+            let who = self.env().caller();
+            if who != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            if !self.withdraw_oracle_refund_() {
+                return Err(Error::RefundPending);
+            }
             Ok(())
         }
 
+        /// Allow the account to call `init_request`.
+        #[ink(message)]
+        pub fn add_requester(&mut self, requester: AccountId) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            self.requesters.insert(requester, ());
+            self.env().emit_event(RequesterAdded { requester });
+            Ok(())
+        }
+
+        /// Disallow the account to call `init_request`, the admin is always allowed.
+        #[ink(message)]
+        pub fn remove_requester(&mut self, requester: AccountId) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            self.requesters.take(&requester);
+            self.env().emit_event(RequesterRemoved { requester });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_requester(&self, account: AccountId) -> bool {
+            account == self.admin || self.requesters.contains_key(&account)
+        }
+
+        /// Change the share of `request_with_funding` payments kept in reserve.
+        #[ink(message)]
+        pub fn set_reserve_bps(&mut self, reserve_bps: u16) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            if reserve_bps > 10_000 {
+                return Err(Error::ValueError);
+            }
+            self.reserve_bps = reserve_bps;
+            Ok(())
+        }
+
+        /// Funds kept in the contract for the next requests.
+        #[ink(message)]
+        pub fn reserve(&self) -> Balance {
+            self.reserve
+        }
+
+        /// Send `amount` of the reserve back to the admin.
+        #[ink(message)]
+        pub fn withdraw_reserve(&mut self, amount: Balance) -> Result<(),Error> {
+            let who = self.env().caller();
+            if who != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            if amount > self.reserve {
+                return Err(Error::ValueError);
+            }
+            if self.env().transfer(who, amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+            self.reserve -= amount;
+            Ok(())
+        }
+
+        /// Change the highest fee a request may forward to the oracle.
+        #[ink(message)]
+        pub fn set_max_fee(&mut self, max_fee: Balance) -> Result<(),Error> {
+            if self.env().caller() != self.admin {
+                return Err(Error::Unauthorized);
+            }
+            self.max_fee = max_fee;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn max_fee(&self) -> Balance {
+            self.max_fee
+        }
+
+        /// Handler tag of an outstanding request.
+        #[ink(message)]
+        pub fn get_handler(&self, request_id: u64) -> Option<u32> {
+            self.outstanding.get(&request_id).copied()
+        }
+
+        /// Latest numeric result delivered to the handler.
+        #[ink(message)]
+        pub fn handler_result(&self, handler_tag: u32) -> Option<i64> {
+            self.handler_results.get(&handler_tag).copied()
+        }
+
         /// This method is called from the Oracle's `callback` fn.
         /// It can be named anything (in this case `set_bitcoin_price`),
         /// however it does need a fixed selector.
         /// The selector value needs to be the same as in the Oracle contract.
+        /// Only results for our outstanding requests are accepted,
+        /// they are routed on the handler tag of the request.
         #[ink(message, selector = "0xB16B00B5")]
-        pub fn set_bitcoin_price(&mut self, result: OracleResult) -> Result<(),Error> {
+        pub fn set_bitcoin_price(&mut self, request_id: u64, result: OracleResult) -> Result<(),Error> {
             // check if the oracle is trusted
             let oracle = self.env().caller();
             if oracle != self.authorized_oracle {
                 return Err(Error::Unauthorized);
             }
 
+            // check if we asked for this result
+            let handler_tag = match self.outstanding.take(&request_id) {
+                Some(handler_tag) => handler_tag,
+                None => return Err(Error::Unauthorized),
+            };
+            self.request_params.take(&request_id);
+            self.env().emit_event(RequestFulfilled { request_id });
+
             // set the oracle's value
-            if let OracleResult::Numeric(price) = result {
-                self.bitcoin_price = price as u64;
+            if let OracleResult::Numeric(value) = result {
+                if handler_tag == BITCOIN_PRICE_HANDLER {
+                    self.bitcoin_price = value as u64;
+                } else {
+                    self.handler_results.insert(handler_tag, value);
+                }
             }
 
             // Let the oracle know all is good
@@ -119,11 +375,143 @@ mod oracle_requester_consumer {
         pub fn its_over_9000(&self) -> bool {
             self.bitcoin_price > 9000 as u64 * 1e8 as u64
         }
+
+        /// Version of the oracle request/callback interface
+        /// spoken by this contract.
+        #[ink(message)]
+        pub fn abi_version(&self) -> u32 {
+            ABI_VERSION
+        }
+
+        /// Fee the oracle currently charges per request.
+        fn oracle_fee_(&self) -> Result<Balance,Error> {
+            use ink_env::call::{build_call, utils::ReturnType, Selector, ExecutionInput};
+            build_call::<ink_env::DefaultEnvironment>()
+                .callee(self.authorized_oracle)
+                .gas_limit(1_000_000)
+                .transferred_value(0)
+                .exec_input(ExecutionInput::new(Selector::new(ORACLE_FEE_SELECTOR)))
+                .returns::<ReturnType<Balance>>()
+                .fire()
+                .map_err(|_| Error::OracleRequestError)
+        }
+
+        /// Reserve left after paying the oracle `fee` from the `funding`
+        /// of `request_with_funding` and the current reserve.
+        fn fund_request_(&self, funding: Balance, fee: Balance) -> Result<Balance,Error> {
+            let reserved = funding
+                .checked_mul(self.reserve_bps as Balance)
+                .ok_or(Error::ValueError)?
+                / 10_000;
+            let available = self.reserve
+                .checked_add(funding - reserved)
+                .ok_or(Error::ValueError)?;
+            let left = available.checked_sub(fee).ok_or(Error::InsufficientReserve)?;
+            left.checked_add(reserved).ok_or(Error::ValueError)
+        }
+
+        /// Withdraw the refunds owed by the oracle, returns whether it paid.
+        fn withdraw_oracle_refund_(&self) -> bool {
+            use ink_env::call::{build_call, utils::ReturnType, Selector, ExecutionInput};
+            let withdrawal = build_call::<ink_env::DefaultEnvironment>()
+                .callee(self.authorized_oracle)
+                .gas_limit(1_000_000)
+                .transferred_value(0)
+                .exec_input(ExecutionInput::new(Selector::new(ORACLE_WITHDRAW_REFUND_SELECTOR)))
+                .returns::<ReturnType<Result<Balance, u8>>>()
+                .fire();
+            matches!(withdrawal, Ok(Ok(_)))
+        }
+
+        /// Parameters of the request resubmitted by `bump_request`, the `tip`
+        /// is added to the previous one and the current `oracle_fee` is paid.
+        fn bump_params_(
+            &self,
+            request_id: u64,
+            tip: Balance,
+            oracle_fee: Balance) -> Result<(Hash, u32, u8, Balance, Balance, Balance, u32),Error> {
+            let (pql, valid_period, priority, callback_value, previous_tip, _) =
+                *self.request_params.get(&request_id).ok_or(Error::AlreadyFulfilled)?;
+            let handler_tag = *self.outstanding.get(&request_id).ok_or(Error::AlreadyFulfilled)?;
+
+            let tip = previous_tip.checked_add(tip).ok_or(Error::ValueError)?;
+            let payment = oracle_fee
+                .checked_add(callback_value)
+                .and_then(|payment| payment.checked_add(tip))
+                .ok_or(Error::ValueError)?;
+            if payment > self.max_fee {
+                return Err(Error::FeeTooHigh);
+            }
+            Ok((pql, valid_period, priority, callback_value, tip, payment, handler_tag))
+        }
+
+        /// Forward the request to the oracle, paying `payment` from the contract.
+        /// It has to cover the oracle fee, the `tip` and the `callback_value`.
+        /// The oracle returns `Result<u64, Error>`, whose error variant
+        /// is scale encoded as a single byte, so we decode it as `u8`.
+        fn request_(
+            &mut self,
+            pql: Hash,
+            valid_period: u32,
+            priority: u8,
+            callback_value: Balance,
+            tip: Balance,
+            payment: Balance,
+            handler_tag: u32) -> Result<u64,Error> {
+            if payment > self.max_fee {
+                return Err(Error::FeeTooHigh);
+            }
+
+            // request data from our oracle
+            use ink_env::call::{build_call, utils::ReturnType, Selector, ExecutionInput};
+            let selector = Selector::new(ORACLE_REQUEST_WITH_TIP_SELECTOR);
+            let request = build_call::<ink_env::DefaultEnvironment>()
+                .callee(self.authorized_oracle)
+                .gas_limit(1_000_000)
+                .transferred_value(payment)
+                .exec_input(ExecutionInput::new(selector)
+                    .push_arg(&pql)
+                    .push_arg(&valid_period)
+                    .push_arg(&priority)
+                    .push_arg(&callback_value)
+                    .push_arg(&tip))
+                .returns::<ReturnType<Result<u64, u8>>>()
+                .fire();
+            match request {
+                Ok(Ok(request_id)) => {
+                    self.outstanding.insert(request_id, handler_tag);
+                    self.request_params.insert(
+                        request_id,
+                        (pql, valid_period, priority, callback_value, tip, payment),
+                    );
+                    Ok(request_id)
+                }
+                _ => Err(Error::OracleRequestError),
+            }
+        }
     }
 
     #[cfg(test)]
     mod tests {
         use super::*;
+        use ink_lang as ink;
+
+        /// Whether the contract has a message with the `selector` taking the `args`,
+        /// decoded the way the contract dispatches the calls
+        fn dispatches(selector: [u8; 4], args: impl scale::Encode) -> bool {
+            let mut input = selector.to_vec();
+            args.encode_to(&mut input);
+            <<OracleRequesterConsumer as ::ink_lang::MessageDispatcher>::Type as scale::Decode>::decode(
+                &mut &input[..],
+            )
+            .is_ok()
+        }
+
+        #[test]
+        fn callback_selector_matches() {
+            assert!(dispatches(CALLBACK_SELECTOR, (1u64, OracleResult::Numeric(42))));
+            assert!(!dispatches([0x00; 4], (1u64, OracleResult::Numeric(42))));
+        }
 
         #[test]
         fn default_works() {
@@ -133,5 +521,176 @@ mod oracle_requester_consumer {
             assert_eq!(c.its_over_9000(), false);
         }
 
+        #[ink::test]
+        fn rejects_fee_over_cap() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain environment should have been initialized already");
+            let mut c = OracleRequesterConsumer::new(accounts.bob, 0, accounts.alice);
+            assert_eq!(c.max_fee(), Balance::MAX);
+            assert_eq!(c.set_max_fee(100), Ok(()));
+            assert_eq!(c.max_fee(), 100);
+
+            // attach more than the cap
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.alice,
+                accounts.eve,
+                1_000_000,
+                101,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+            let pql = Hash::from([0x01; 32]);
+            assert_eq!(c.init_request(pql, 10, 0, 0, BITCOIN_PRICE_HANDLER), Err(Error::FeeTooHigh));
+        }
+
+        #[ink::test]
+        fn manages_requesters() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain environment should have been initialized already");
+            let mut c = OracleRequesterConsumer::new(accounts.eve, 0, accounts.alice);
+            assert!(c.is_requester(accounts.alice));
+            assert!(!c.is_requester(accounts.bob));
+            assert_eq!(c.add_requester(accounts.bob), Ok(()));
+            assert!(c.is_requester(accounts.bob));
+            assert_eq!(ink_env::test::recorded_events().count(), 1);
+
+            // bob is let trough to the fee check
+            let pql = Hash::from([0x01; 32]);
+            assert_eq!(c.set_max_fee(0), Ok(()));
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.bob,
+                accounts.eve,
+                1_000_000,
+                1,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+            assert_eq!(c.init_request(pql, 10, 0, 0, BITCOIN_PRICE_HANDLER), Err(Error::FeeTooHigh));
+            assert_eq!(c.add_requester(accounts.charlie), Err(Error::Unauthorized));
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.alice,
+                accounts.eve,
+                1_000_000,
+                0,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+
+            assert_eq!(c.remove_requester(accounts.bob), Ok(()));
+            assert!(!c.is_requester(accounts.bob));
+        }
+
+        #[ink::test]
+        fn accepts_only_outstanding_requests() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain environment should have been initialized already");
+            let mut c = OracleRequesterConsumer::new(accounts.alice, 0, accounts.alice);
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(42)), Err(Error::Unauthorized));
+
+            c.outstanding.insert(1, BITCOIN_PRICE_HANDLER);
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(42)), Ok(()));
+            assert_eq!(c.bitcoin_price, 42);
+
+            // answered already
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(43)), Err(Error::Unauthorized));
+            assert_eq!(c.bitcoin_price, 42);
+        }
+
+        #[ink::test]
+        fn routes_results_on_handler_tag() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain environment should have been initialized already");
+            let mut c = OracleRequesterConsumer::new(accounts.alice, 0, accounts.alice);
+            c.outstanding.insert(1, 7);
+            assert_eq!(c.get_handler(1), Some(7));
+            assert_eq!(c.get_handler(2), None);
+
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(42)), Ok(()));
+            assert_eq!(c.handler_result(7), Some(42));
+            assert_eq!(c.bitcoin_price, 0);
+            assert_eq!(c.get_handler(1), None);
+        }
+
+        #[ink::test]
+        fn does_not_bump_fulfilled_requests() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain environment should have been initialized already");
+            let mut c = OracleRequesterConsumer::new(accounts.alice, 0, accounts.alice);
+            assert_eq!(c.bump_request(1), Err(Error::AlreadyFulfilled));
+
+            c.outstanding.insert(1, BITCOIN_PRICE_HANDLER);
+            c.request_params.insert(1, (Hash::from([0x01; 32]), 10, 0, 0, 0, 100));
+            assert_eq!(c.set_bitcoin_price(1, OracleResult::Numeric(42)), Ok(()));
+            assert_eq!(c.bump_request(1), Err(Error::AlreadyFulfilled));
+        }
+
+        #[ink::test]
+        fn withdraws_reserve() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain environment should have been initialized already");
+            let mut c = OracleRequesterConsumer::new(accounts.eve, 0, accounts.alice);
+            let contract = ink_env::test::get_current_contract_account_id::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get contract id");
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract, 100)
+                .expect("Cannot set account balance");
+            c.reserve = 100;
+
+            assert_eq!(c.withdraw_reserve(101), Err(Error::ValueError));
+            assert_eq!(c.withdraw_reserve(60), Ok(()));
+            assert_eq!(c.reserve(), 40);
+
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.bob,
+                accounts.eve,
+                1_000_000,
+                0,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+            assert_eq!(c.withdraw_reserve(40), Err(Error::Unauthorized));
+        }
+
+        #[ink::test]
+        fn pays_fee_from_reserve() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain environment should have been initialized already");
+            let mut c = OracleRequesterConsumer::new(accounts.eve, 0, accounts.alice);
+            assert_eq!(c.set_reserve_bps(2_000), Ok(()));
+
+            // the oracle can't be called off-chain, so only the split is checked.
+            // 30 are set aside and the 20 left over from the fee of 100 are kept
+            assert_eq!(c.fund_request_(150, 100), Ok(50));
+            c.reserve = 50;
+
+            // the reserve pays the 44 missing for the second request
+            assert_eq!(c.fund_request_(70, 100), Ok(20));
+            c.reserve = 20;
+            assert_eq!(c.fund_request_(0, 100), Err(Error::InsufficientReserve));
+            assert_eq!(c.fund_request_(Balance::MAX, 0), Err(Error::ValueError));
+        }
+
+        #[ink::test]
+        fn bumps_with_tip() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain environment should have been initialized already");
+            let mut c = OracleRequesterConsumer::new(accounts.alice, 0, accounts.alice);
+            let pql = Hash::from([0x01; 32]);
+            c.outstanding.insert(1, BITCOIN_PRICE_HANDLER);
+            c.request_params.insert(1, (pql, 10, 0, 10, 3, 113));
+
+            // the oracle can't be called off-chain, so only the parameters are checked.
+            // the tip adds to the previous one and raises the oracle's fee,
+            // the callback value stays the same
+            assert_eq!(
+                c.bump_params_(1, 5, 100),
+                Ok((pql, 10, 0, 10, 8, 118, BITCOIN_PRICE_HANDLER))
+            );
+            // the current fee of the oracle is paid
+            assert_eq!(
+                c.bump_params_(1, 5, 120),
+                Ok((pql, 10, 0, 10, 8, 138, BITCOIN_PRICE_HANDLER))
+            );
+
+            assert_eq!(c.set_max_fee(117), Ok(()));
+            assert_eq!(c.bump_params_(1, 5, 100), Err(Error::FeeTooHigh));
+            assert_eq!(c.bump_params_(1, Balance::MAX, 100), Err(Error::ValueError));
+        }
+
     }
 }
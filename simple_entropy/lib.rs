@@ -4,13 +4,19 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod simple_entropy {
+    use ink_prelude::vec::Vec;
     use ink_storage::collections::{HashMap};
 
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
     #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
     pub enum Error {
         RequestAlreadyExists,
+        RequestNotFound,
         PermissionDenied,
+        ResultExpired,
+        ResultNotExpired,
+        InvalidResult,
+        StaleWrite,
     }
 
     #[ink(event)]
@@ -20,20 +26,30 @@ mod simple_entropy {
         request_id: Hash,
     }
 
+    #[ink(event)]
+    pub struct ResultWritten {
+        #[ink(topic)]
+        request_id: Hash,
+    }
+
     #[ink(storage)]
     pub struct SimpleEntropy {
         owner: AccountId,
-        // HashMap<request_id, result>
-        requests: HashMap<Hash, Hash>,
+        // HashMap<request_id, (result, written_at_block, version)>,
+        // written_at_block is 0 until a result is written
+        requests: HashMap<Hash, (Hash, u64, u32)>,
+        // Number of blocks a result stays valid, 0 means forever
+        ttl_blocks: u64,
     }
 
     impl SimpleEntropy {
 
         #[ink(constructor)]
-        pub fn new(owner: AccountId) -> Self {
+        pub fn new(owner: AccountId, ttl_blocks: u64) -> Self {
             Self {
                 owner: owner,
                 requests: HashMap::new(),
+                ttl_blocks,
             }
         }
 
@@ -42,13 +58,31 @@ mod simple_entropy {
             Self {
                 owner: Self::env().caller(),
                 requests: Default::default(),
+                ttl_blocks: 0,
             }
         }
 
+        /// Returns the zero hash while the request is unanswered,
+        /// the zero hash is never accepted as a result.
         #[ink(message)]
-        pub fn get_result(&self, request_id: Hash) -> Hash {
-            let result = self.requests.get(&request_id).unwrap();
-            *result
+        pub fn get_result(&self, request_id: Hash) -> Result<Hash,Error> {
+            let (result, written_at, _) = self.requests.get(&request_id).ok_or(Error::RequestNotFound)?;
+            if self.is_expired(result, *written_at) {
+                return Err(Error::ResultExpired);
+            }
+            Ok(*result)
+        }
+
+        /// Results of many requests in the order of `ids`,
+        /// `None` for unknown, unanswered or expired requests
+        #[ink(message)]
+        pub fn get_results(&self, ids: Vec<Hash>) -> Vec<Option<Hash>> {
+            ids.iter()
+                .map(|request_id| match self.get_result(*request_id) {
+                    Ok(result) if result != Self::placeholder() => Some(result),
+                    _ => None,
+                })
+                .collect()
         }
 
         #[ink(message)]
@@ -58,23 +92,104 @@ mod simple_entropy {
             if self.requests.contains_key(&request_id) {
                 return Err(Error::RequestAlreadyExists);
             } else {
-                self.requests.insert(request_id, Hash::from([0x00; 32]));
+                self.requests.insert(request_id, (Self::placeholder(), 0, 0));
                 self.env().emit_event(Request { from: caller, request_id: request_id});
             }
             Ok(())
         }
 
+        /// Number of times a result was written for the request
+        #[ink(message)]
+        pub fn get_version(&self, request_id: Hash) -> u32 {
+            self.requests.get(&request_id).map(|entry| entry.2).unwrap_or(0)
+        }
+
+        /// Write the result, `expected_version` has to match `get_version`,
+        /// otherwise a newer result was written meanwhile.
         #[ink(message)]
-        pub fn write_result(&mut self, request_id: Hash, result: Hash) -> Result<(),Error> {
+        pub fn write_result(&mut self, request_id: Hash, result: Hash, expected_version: u32) -> Result<(),Error> {
             let caller = self.env().caller();
+            if result == Self::placeholder() {
+                return Err(Error::InvalidResult);
+            }
+            if !self.requests.contains_key(&request_id) {
+                return Err(Error::RequestNotFound);
+            }
+            if self.get_version(request_id) != expected_version {
+                return Err(Error::StaleWrite);
+            }
             if caller == self.owner {
-                self.requests.insert(request_id, result);
+                self.write_result_(request_id, result);
             } else {
                 return Err(Error::PermissionDenied);
             }
             Ok(())
         }
 
+        /// Write many `(request_id, result, expected_version)` entries at once.
+        /// The whole batch is rejected with `RequestNotFound` if any of
+        /// the ids was never requested, or with `StaleWrite` if any of the
+        /// versions doesn't match, in which case nothing is written.
+        #[ink(message)]
+        pub fn write_results(&mut self, entries: Vec<(Hash, Hash, u32)>) -> Result<(),Error> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::PermissionDenied);
+            }
+
+            if entries.iter().any(|(request_id, _, _)| !self.requests.contains_key(request_id)) {
+                return Err(Error::RequestNotFound);
+            }
+            if entries.iter().any(|(_, result, _)| *result == Self::placeholder()) {
+                return Err(Error::InvalidResult);
+            }
+            if entries
+                .iter()
+                .any(|(request_id, _, expected_version)| self.get_version(*request_id) != *expected_version)
+            {
+                return Err(Error::StaleWrite);
+            }
+            for (request_id, result, _) in entries {
+                self.write_result_(request_id, result);
+            }
+            Ok(())
+        }
+
+        /// Remove an expired result to reclaim contract storage,
+        /// unanswered requests never expire
+        #[ink(message)]
+        pub fn purge(&mut self, request_id: Hash) -> Result<(),Error> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::PermissionDenied);
+            }
+
+            let (result, written_at, _) = self.requests.get(&request_id).ok_or(Error::RequestNotFound)?;
+            if !self.is_expired(result, *written_at) {
+                return Err(Error::ResultNotExpired);
+            }
+            self.requests.take(&request_id);
+            Ok(())
+        }
+
+        /// Result of a request which has not been answered yet
+        fn placeholder() -> Hash {
+            Hash::from([0x00; 32])
+        }
+
+        fn write_result_(&mut self, request_id: Hash, result: Hash) {
+            let version = self.get_version(request_id) + 1;
+            self.requests.insert(request_id, (result, self.env().block_number(), version));
+            self.env().emit_event(ResultWritten { request_id });
+        }
+
+        /// The TTL only starts once a result is written
+        fn is_expired(&self, result: &Hash, written_at: u64) -> bool {
+            *result != Self::placeholder()
+                && self.ttl_blocks > 0
+                && self.env().block_number().saturating_sub(written_at) > self.ttl_blocks
+        }
+
     }
 
     #[cfg(test)]
@@ -85,7 +200,7 @@ mod simple_entropy {
         #[ink::test]
         fn it_sets_owner() {
             let owner = AccountId::from([0x0; 32]);
-            let c = SimpleEntropy::new(owner);
+            let c = SimpleEntropy::new(owner, 0);
             assert_eq!(c.owner, owner);
         }
 
@@ -95,7 +210,7 @@ mod simple_entropy {
             let request_id = Hash::from([0x01; 32]);
             assert_eq!(c.make_request(request_id), Ok(()));
             assert_eq!(c.make_request(request_id), Err(Error::RequestAlreadyExists));
-            assert_eq!(c.get_result(request_id), Hash::from([0x00; 32]));
+            assert_eq!(c.get_result(request_id), Ok(Hash::from([0x00; 32])));
         }
 
         #[ink::test]
@@ -104,9 +219,41 @@ mod simple_entropy {
             let request_id = Hash::from([0x01; 32]);
             let result = Hash::from([0x42; 32]);
             assert_eq!(c.make_request(request_id), Ok(()));
-            assert_eq!(c.get_result(request_id), Hash::from([0x00; 32]));
-            assert_eq!(c.write_result(request_id, result), Ok(()));
-            assert_eq!(c.get_result(request_id), result);
+            assert_eq!(c.get_result(request_id), Ok(Hash::from([0x00; 32])));
+            assert_eq!(c.write_result(request_id, result, 0), Ok(()));
+            assert_eq!(c.get_result(request_id), Ok(result));
+
+            // never requested
+            let unknown = Hash::from([0x02; 32]);
+            assert_eq!(c.write_result(unknown, result, 0), Err(Error::RequestNotFound));
+            assert_eq!(c.get_result(unknown), Err(Error::RequestNotFound));
+        }
+
+        #[ink::test]
+        fn it_rejects_stale_write() {
+            let mut c = SimpleEntropy::default();
+            let request_id = Hash::from([0x01; 32]);
+            let result = Hash::from([0x42; 32]);
+            let newer = Hash::from([0x43; 32]);
+            assert_eq!(c.make_request(request_id), Ok(()));
+            assert_eq!(c.write_result(request_id, newer, 0), Ok(()));
+            assert_eq!(c.get_version(request_id), 1);
+
+            // the slow worker still expects the first version
+            assert_eq!(c.write_result(request_id, result, 0), Err(Error::StaleWrite));
+            assert_eq!(c.get_result(request_id), Ok(newer));
+        }
+
+        #[ink::test]
+        fn it_rejects_placeholder_result() {
+            let mut c = SimpleEntropy::default();
+            let request_id = Hash::from([0x01; 32]);
+            assert_eq!(c.make_request(request_id), Ok(()));
+            assert_eq!(c.write_result(request_id, Hash::from([0x00; 32]), 0), Err(Error::InvalidResult));
+            assert_eq!(
+                c.write_results(vec![(request_id, Hash::from([0x00; 32]), 0)]),
+                Err(Error::InvalidResult)
+            );
         }
 
         #[ink::test]
@@ -114,19 +261,124 @@ mod simple_entropy {
             // alice is admin
             let accounts = default_accounts();
             set_next_caller(accounts.alice);
-            let mut c = SimpleEntropy::new(accounts.alice);
+            let mut c = SimpleEntropy::new(accounts.alice, 0);
             assert_eq!(c.owner, accounts.alice);
 
             let request_id = Hash::from([0x01; 32]);
             let result = Hash::from([0x42; 32]);
             assert_eq!(c.make_request(request_id), Ok(()));
-            assert_eq!(c.get_result(request_id), Hash::from([0x00; 32]));
+            assert_eq!(c.get_result(request_id), Ok(Hash::from([0x00; 32])));
 
             // bob tries to answer
             set_next_caller(accounts.bob);
 
-            assert_eq!(c.write_result(request_id, result), Err(Error::PermissionDenied));
-            assert_eq!(c.get_result(request_id), Hash::from([0x00; 32]));
+            assert_eq!(c.write_result(request_id, result, 0), Err(Error::PermissionDenied));
+            assert_eq!(c.get_result(request_id), Ok(Hash::from([0x00; 32])));
+        }
+
+        #[ink::test]
+        fn it_expires_result() {
+            let accounts = default_accounts();
+            set_next_caller(accounts.alice);
+            let mut c = SimpleEntropy::new(accounts.alice, 2);
+
+            let request_id = Hash::from([0x01; 32]);
+            let result = Hash::from([0x42; 32]);
+            assert_eq!(c.make_request(request_id), Ok(()));
+            assert_eq!(c.write_result(request_id, result, 0), Ok(()));
+            assert_eq!(c.purge(request_id), Err(Error::ResultNotExpired));
+
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(c.get_result(request_id), Err(Error::ResultExpired));
+            assert_eq!(c.purge(request_id), Ok(()));
+            assert_eq!(c.get_result(request_id), Err(Error::RequestNotFound));
+        }
+
+        #[ink::test]
+        fn it_starts_ttl_on_write() {
+            let accounts = default_accounts();
+            set_next_caller(accounts.alice);
+            let mut c = SimpleEntropy::new(accounts.alice, 2);
+
+            let request_id = Hash::from([0x01; 32]);
+            let result = Hash::from([0x42; 32]);
+            assert_eq!(c.make_request(request_id), Ok(()));
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            // unanswered requests don't expire
+            assert_eq!(c.get_result(request_id), Ok(Hash::from([0x00; 32])));
+            assert_eq!(c.purge(request_id), Err(Error::ResultNotExpired));
+
+            assert_eq!(c.write_result(request_id, result, 0), Ok(()));
+            assert_eq!(c.get_result(request_id), Ok(result));
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(c.get_result(request_id), Err(Error::ResultExpired));
+        }
+
+        #[ink::test]
+        fn it_returns_results_in_bulk() {
+            let mut c = SimpleEntropy::default();
+            let first = Hash::from([0x01; 32]);
+            let second = Hash::from([0x02; 32]);
+            let unknown = Hash::from([0x03; 32]);
+            let result = Hash::from([0x42; 32]);
+            assert_eq!(c.make_request(first), Ok(()));
+            assert_eq!(c.make_request(second), Ok(()));
+            assert_eq!(c.write_result(second, result, 0), Ok(()));
+            assert_eq!(
+                c.get_results(vec![second, first, unknown]),
+                vec![Some(result), None, None]
+            );
+        }
+
+        #[ink::test]
+        fn it_accepts_results_in_bulk() {
+            let mut c = SimpleEntropy::default();
+            let first = Hash::from([0x01; 32]);
+            let second = Hash::from([0x02; 32]);
+            let result = Hash::from([0x42; 32]);
+            assert_eq!(c.make_request(first), Ok(()));
+            assert_eq!(c.make_request(second), Ok(()));
+
+            // unknown id rejects the whole batch
+            let unknown = Hash::from([0x03; 32]);
+            assert_eq!(
+                c.write_results(vec![(first, result, 0), (unknown, result, 0)]),
+                Err(Error::RequestNotFound)
+            );
+            assert_eq!(c.get_result(first), Ok(Hash::from([0x00; 32])));
+
+            assert_eq!(c.write_results(vec![(first, result, 0), (second, result, 0)]), Ok(()));
+            assert_eq!(c.get_result(first), Ok(result));
+            assert_eq!(c.get_result(second), Ok(result));
+        }
+
+        #[ink::test]
+        fn it_rejects_stale_batch() {
+            let mut c = SimpleEntropy::default();
+            let first = Hash::from([0x01; 32]);
+            let second = Hash::from([0x02; 32]);
+            let result = Hash::from([0x42; 32]);
+            let newer = Hash::from([0x43; 32]);
+            assert_eq!(c.make_request(first), Ok(()));
+            assert_eq!(c.make_request(second), Ok(()));
+            assert_eq!(c.write_result(second, newer, 0), Ok(()));
+
+            // one stale entry rejects the whole batch
+            assert_eq!(
+                c.write_results(vec![(first, result, 0), (second, result, 0)]),
+                Err(Error::StaleWrite)
+            );
+            assert_eq!(c.get_result(first), Ok(Hash::from([0x00; 32])));
+            assert_eq!(c.get_result(second), Ok(newer));
+
+            assert_eq!(c.write_results(vec![(first, result, 0), (second, result, 1)]), Ok(()));
+            assert_eq!(c.get_version(second), 2);
         }
 
 
@@ -4,30 +4,155 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod request_etl {
+    use ink_storage::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    pub enum Error {
+        DuplicateRequest,
+        PermissionDenied,
+        Paused,
+        /// No request ids are left
+        RequestIdOverflow,
+    }
 
     #[ink(event)]
-    pub struct Request {
+    pub struct RequestV2 {
         #[ink(topic)]
         from: AccountId,
+        /// Sequential id of the request, indexers can detect gaps with it
+        request_id: u64,
         /// PQL ETL Definition
         /// Skip first 2 bytes (hash fn, size) so that we can fit into bytes32
         ipfs_hash: Hash,
+        /// Client supplied key, repeated events with the same key
+        /// come from retried transactions
+        dedup_key: Option<u64>,
+        /// Kind of the job, 0 if not specified
+        job_type: u32,
+        /// Max fee the caller is willing to pay off-chain, 0 if not specified
+        max_fee: u64,
+        /// Block the request was made in
+        block: u64,
+    }
+
+    #[ink(event)]
+    pub struct Paused {
+        #[ink(topic)]
+        by: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct Unpaused {
+        #[ink(topic)]
+        by: AccountId,
     }
 
     #[ink(storage)]
-    pub struct RequestEtl { }
+    pub struct RequestEtl {
+        /// Owner of the contract, can pause the requests
+        owner: AccountId,
+        /// No requests are accepted while paused
+        paused: bool,
+        /// Number of requests made so far
+        request_count: u64,
+        /// Dedup keys already used by each caller
+        dedup_keys: HashMap<(AccountId, u64), ()>,
+    }
 
     impl RequestEtl {
 
         #[ink(constructor)]
         pub fn new() -> Self {
-            Self {}
+            Self {
+                owner: Self::env().caller(),
+                paused: false,
+                request_count: 0,
+                dedup_keys: HashMap::new(),
+            }
+        }
+
+        /// Request the ETL job, returns the id of the request.
+        #[ink(message)]
+        pub fn request(&mut self, ipfs_hash: Hash) -> Result<u64, Error> {
+            if self.paused {
+                return Err(Error::Paused);
+            }
+            self.emit_request_(ipfs_hash, None, 0, 0)
         }
 
+        /// Same as `request`, but describes the job for the off-chain workers.
         #[ink(message)]
-        pub fn request(&mut self, ipfs_hash: Hash) {
+        pub fn request_job(&mut self, ipfs_hash: Hash, job_type: u32, max_fee: u64) -> Result<u64, Error> {
+            if self.paused {
+                return Err(Error::Paused);
+            }
+            self.emit_request_(ipfs_hash, None, job_type, max_fee)
+        }
+
+        /// Same as `request`, but rejects a repeated `dedup_key` from the same caller.
+        #[ink(message)]
+        pub fn request_with_key(&mut self, ipfs_hash: Hash, dedup_key: u64) -> Result<u64, Error> {
+            if self.paused {
+                return Err(Error::Paused);
+            }
             let from = self.env().caller();
-            self.env().emit_event(Request{from, ipfs_hash});
+            if self.dedup_keys.contains_key(&(from, dedup_key)) {
+                return Err(Error::DuplicateRequest);
+            }
+            let request_id = self.emit_request_(ipfs_hash, Some(dedup_key), 0, 0)?;
+            self.dedup_keys.insert((from, dedup_key), ());
+            Ok(request_id)
+        }
+
+        /// Stop accepting requests
+        #[ink(message)]
+        pub fn pause(&mut self) -> Result<(), Error> {
+            let by = self.env().caller();
+            if by != self.owner {
+                return Err(Error::PermissionDenied);
+            }
+            self.paused = true;
+            self.env().emit_event(Paused { by });
+            Ok(())
+        }
+
+        /// Start accepting requests again
+        #[ink(message)]
+        pub fn unpause(&mut self) -> Result<(), Error> {
+            let by = self.env().caller();
+            if by != self.owner {
+                return Err(Error::PermissionDenied);
+            }
+            self.paused = false;
+            self.env().emit_event(Unpaused { by });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_paused(&self) -> bool {
+            self.paused
+        }
+
+        /// Id of the last request, indexers resume from here
+        #[ink(message)]
+        pub fn checkpoint(&self) -> u64 {
+            self.request_count
+        }
+
+        fn emit_request_(&mut self, ipfs_hash: Hash, dedup_key: Option<u64>, job_type: u32, max_fee: u64) -> Result<u64, Error> {
+            self.request_count =
+                mathutil::checked_next_id(self.request_count).ok_or(Error::RequestIdOverflow)?;
+            self.env().emit_event(RequestV2 {
+                from: self.env().caller(),
+                request_id: self.request_count,
+                ipfs_hash,
+                dedup_key,
+                job_type,
+                max_fee,
+                block: self.env().block_number(),
+            });
+            Ok(self.request_count)
         }
 
     }
@@ -47,7 +172,87 @@ mod request_etl {
             let input = "42978b1c54ad19f93da7dbc05d0f023062256e95360dfba06c09c1605da75a1b";
             let decoded = <[u8; 32]>::from_hex(input).expect("Decoding failed");
             let ipfs_hash = Hash::from(decoded);
-            request_etl.request(ipfs_hash);
+            assert_eq!(request_etl.request(ipfs_hash), Ok(1));
+        }
+
+        #[ink::test]
+        fn it_rejects_duplicate_keys() {
+            let mut request_etl = RequestEtl::new();
+            let ipfs_hash = Hash::from([0x01; 32]);
+            assert_eq!(request_etl.request_with_key(ipfs_hash, 1), Ok(1));
+            assert_eq!(request_etl.request_with_key(ipfs_hash, 1), Err(Error::DuplicateRequest));
+            assert_eq!(request_etl.request_with_key(ipfs_hash, 2), Ok(2));
+
+            // no key, no dedup
+            assert_eq!(request_etl.request(ipfs_hash), Ok(3));
+            assert_eq!(request_etl.request(ipfs_hash), Ok(4));
+        }
+
+        #[ink::test]
+        fn it_pauses_requests() {
+            let mut request_etl = RequestEtl::new();
+            let ipfs_hash = Hash::from([0x01; 32]);
+            assert_eq!(request_etl.pause(), Ok(()));
+            assert!(request_etl.is_paused());
+            assert_eq!(request_etl.request(ipfs_hash), Err(Error::Paused));
+            assert_eq!(request_etl.request_with_key(ipfs_hash, 1), Err(Error::Paused));
+
+            assert_eq!(request_etl.unpause(), Ok(()));
+            assert_eq!(request_etl.request(ipfs_hash), Ok(1));
+        }
+
+        #[ink::test]
+        fn it_emits_job_details() {
+            let mut request_etl = RequestEtl::new();
+            let ipfs_hash = Hash::from([0x01; 32]);
+            assert_eq!(request_etl.request(ipfs_hash), Ok(1));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            let expected_block = ink_env::block_number::<ink_env::DefaultEnvironment>().unwrap();
+            assert_eq!(request_etl.request_job(ipfs_hash, 3, 1_000), Ok(2));
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(emitted_events.len(), 2);
+            let event = emitted_events.last().unwrap();
+            let decoded_event = <Event as scale::Decode>::decode(&mut &event.data[..])
+                .expect("encountered invalid contract event data buffer");
+            if let Event::RequestV2(RequestV2 { request_id, job_type, max_fee, block, .. }) = decoded_event {
+                assert_eq!(request_id, 2);
+                assert_eq!(job_type, 3);
+                assert_eq!(max_fee, 1_000);
+                assert_eq!(block, expected_block);
+            } else {
+                panic!("encountered unexpected event kind: expected a RequestV2 event")
+            }
+        }
+
+        #[ink::test]
+        fn it_counts_requests() {
+            let mut request_etl = RequestEtl::new();
+            let ipfs_hash = Hash::from([0x01; 32]);
+            assert_eq!(request_etl.checkpoint(), 0);
+            assert_eq!(request_etl.request(ipfs_hash), Ok(1));
+            assert_eq!(request_etl.request_with_key(ipfs_hash, 1), Ok(2));
+            assert_eq!(request_etl.checkpoint(), 2);
+
+            // rejected requests don't count
+            assert_eq!(request_etl.request_with_key(ipfs_hash, 1), Err(Error::DuplicateRequest));
+            assert_eq!(request_etl.checkpoint(), 2);
+        }
+
+        #[ink::test]
+        fn it_rejects_requests_after_the_last_id() {
+            let mut request_etl = RequestEtl::new();
+            let ipfs_hash = Hash::from([0x01; 32]);
+            request_etl.request_count = u64::MAX;
+            assert_eq!(request_etl.request(ipfs_hash), Err(Error::RequestIdOverflow));
+            assert_eq!(request_etl.request_job(ipfs_hash, 3, 1_000), Err(Error::RequestIdOverflow));
+            assert_eq!(request_etl.request_with_key(ipfs_hash, 1), Err(Error::RequestIdOverflow));
+            assert_eq!(request_etl.checkpoint(), u64::MAX);
+            assert_eq!(ink_env::test::recorded_events().count(), 0);
+
+            // the key of the failed request isn't used up
+            request_etl.request_count = 0;
+            assert_eq!(request_etl.request_with_key(ipfs_hash, 1), Ok(1));
         }
     }
 }
@@ -4,8 +4,13 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod simple_rng {
+    use ink_prelude::vec::Vec;
     use ink_storage::collections::{HashMap};
 
+    /// Hash algorithms the revealed results can be committed with
+    pub const HASH_ALG_BLAKE2: u8 = 0;
+    pub const HASH_ALG_KECCAK: u8 = 1;
+
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
     #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
     pub enum Error {
@@ -14,6 +19,9 @@ mod simple_rng {
         DuplicateResult,
         InvalidRequest,
         InvalidResult,
+        InvalidRange,
+        NotFinalized,
+        CommitmentMismatch,
     }
 
     #[ink(event)]
@@ -23,25 +31,76 @@ mod simple_rng {
         request_id: u64,
     }
 
+    #[ink(event)]
+    pub struct RequestRerolled {
+        #[ink(topic)]
+        from: AccountId,
+        request_id: u64,
+        min: u32,
+        max: u32,
+    }
+
+    #[ink(event)]
+    pub struct RequestCancelled {
+        #[ink(topic)]
+        from: AccountId,
+        request_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct AnswererChanged {
+        #[ink(topic)]
+        answerer: AccountId,
+    }
+
     #[ink(storage)]
     pub struct SimpleRNG {
         owner: AccountId,
+        // account allowed to write the results, managed by the owner
+        answerer: AccountId,
         request_id: u64,
-        // HashMap<request_id, (min, max)>
-        requests: HashMap<u64, (u32, u32)>,
-        // HashMap<request_id, randint>
-        results: HashMap<u64, u32>
+        // HashMap<request_id, (min, max, requester)>
+        requests: HashMap<u64, (u32, u32, AccountId)>,
+        // HashMap<request_id, (randint, written_at_block)>
+        results: HashMap<u64, (u32, u64)>,
+        // whether requests with min == max are accepted
+        allow_fixed_range: bool,
+        // number of requests awaiting a result
+        pending: u64,
+        // compressed ECDSA public key of the owner for relayed results
+        owner_pubkey: [u8; 33],
+        // number of blocks before a written result is final
+        confirmations: u64,
+        // HASH_ALG_* the commitments are verified with
+        hash_alg: u8,
+        // HashMap<request_id, hash of the result and salt>
+        commitments: HashMap<u64, Hash>,
+        // HashMap<hash of (requester, client_nonce), request_id>
+        request_keys: HashMap<Hash, u64>,
     }
 
     impl SimpleRNG {
 
+        /// Unknown `hash_alg` values fall back to `HASH_ALG_BLAKE2`
         #[ink(constructor)]
-        pub fn new(owner: AccountId) -> Self {
+        pub fn new(owner: AccountId, hash_alg: u8) -> Self {
+            let hash_alg = match hash_alg {
+                HASH_ALG_KECCAK => HASH_ALG_KECCAK,
+                _ => HASH_ALG_BLAKE2,
+            };
             Self {
                 owner: owner,
+                answerer: owner,
                 request_id: 0,
                 requests: HashMap::new(),
                 results: HashMap::new(),
+                allow_fixed_range: false,
+                pending: 0,
+                owner_pubkey: [0x00; 33],
+                confirmations: 0,
+                hash_alg,
+                commitments: HashMap::new(),
+                request_keys: HashMap::new(),
             }
         }
 
@@ -49,52 +108,308 @@ mod simple_rng {
         pub fn default() -> Self {
             Self {
                 owner: Self::env().caller(),
+                answerer: Self::env().caller(),
                 request_id: 0,
                 requests: Default::default(),
                 results: Default::default(),
+                allow_fixed_range: false,
+                pending: 0,
+                owner_pubkey: [0x00; 33],
+                confirmations: 0,
+                hash_alg: HASH_ALG_BLAKE2,
+                commitments: Default::default(),
+                request_keys: Default::default(),
             }
         }
 
+        /// Results are only returned once they have `confirmations` blocks
         #[ink(message)]
         pub fn get_result(&self, request_id: u64) -> Result<u32,Error> {
-            if let Some(result) = self.results.get(&request_id) {
+            if let Some((result, written_at)) = self.results.get(&request_id) {
+                if self.env().block_number() < written_at.saturating_add(self.confirmations) {
+                    return Err(Error::NotFinalized);
+                }
                 Ok(*result)
             } else {
                 Err(Error::ResultNotFound)
             }
         }
 
+        /// Results of many requests in the order of `ids`,
+        /// `None` for the ones without a final result
+        #[ink(message)]
+        pub fn get_results(&self, ids: Vec<u64>) -> Vec<Option<u32>> {
+            ids.iter().map(|request_id| self.get_result(*request_id).ok()).collect()
+        }
+
+        /// Whether the result was written and has enough confirmations
+        #[ink(message)]
+        pub fn is_final(&self, request_id: u64) -> bool {
+            self.get_result(request_id).is_ok()
+        }
+
+        /// Range `(min, max)` the result of the request must satisfy
+        #[ink(message)]
+        pub fn get_range(&self, request_id: u64) -> Option<(u32, u32)> {
+            self.requests.get(&request_id).map(|(min, max, _)| (*min, *max))
+        }
+
+        /// Range of the request and whether it was answered, as
+        /// `(min, max, answered)`
+        #[ink(message)]
+        pub fn get_request(&self, request_id: u64) -> Option<(u32, u32, bool)> {
+            let (min, max) = self.get_range(request_id)?;
+            Some((min, max, self.results.contains_key(&request_id)))
+        }
+
+        /// Number of requests awaiting a result
+        #[ink(message)]
+        pub fn pending_count(&self) -> u64 {
+            self.pending
+        }
+
         #[ink(message)]
         pub fn make_request(&mut self, min: u32, max: u32) -> Result<u64,Error> {
             let caller = self.env().caller();
-            // loop around to 0 after u64::max_value() is reached
-            self.request_id = self.request_id.wrapping_add(1);
-            self.requests.insert(self.request_id, (min, max));
+
+            if min > max || (min == max && !self.allow_fixed_range) {
+                return Err(Error::InvalidRange);
+            }
+            self.request_id =
+                mathutil::checked_next_id(self.request_id).ok_or(Error::InvalidRequest)?;
+            self.requests.insert(self.request_id, (min, max, caller));
+            self.pending += 1;
             self.env().emit_event(Request { from: caller, request_id: self.request_id});
             Ok(self.request_id)
         }
 
+        /// Same as `make_request`, but a retried request with the same
+        /// `client_nonce` returns the id of the existing request
+        #[ink(message)]
+        pub fn make_request_keyed(&mut self, min: u32, max: u32, client_nonce: u64) -> Result<u64,Error> {
+            let caller = self.env().caller();
+            let key = Hash::from(
+                self.env().hash_encoded::<ink_env::hash::Blake2x256, _>(&(caller, client_nonce)),
+            );
+            if let Some(request_id) = self.request_keys.get(&key) {
+                return Ok(*request_id);
+            }
+
+            let request_id = self.make_request(min, max)?;
+            self.request_keys.insert(key, request_id);
+            Ok(request_id)
+        }
+
         #[ink(message)]
         pub fn write_result(&mut self, request_id: u64, randint: u32) -> Result<(),Error> {
             let caller = self.env().caller();
+            self.write_result_(request_id, randint, caller == self.answerer)
+        }
+
+        /// Write a result relayed by anyone on behalf of the owner.
+        /// The signature is over the blake2 hash of the encoded
+        /// `(contract_id, request_id, randint)` tuple.
+        #[ink(message)]
+        pub fn write_result_signed(&mut self, request_id: u64, randint: u32, signature: [u8; 65]) -> Result<(),Error> {
+            let message = (self.env().account_id(), request_id, randint);
+            let message_hash = self.env().hash_encoded::<ink_env::hash::Blake2x256, _>(&message);
+
+            let authorized = self.owner_pubkey != [0x00; 33]
+                && Self::recover_signer_(&message_hash, &signature) == Some(self.owner_pubkey);
+            self.write_result_(request_id, randint, authorized)
+        }
+
+        /// Commit to a result before revealing it, the commitment is the
+        /// hash of the little endian `randint` followed by the `salt`
+        #[ink(message)]
+        pub fn commit_result(&mut self, request_id: u64, commitment: Hash) -> Result<(),Error> {
+            let caller = self.env().caller();
+            if caller != self.answerer {
+                return Err(Error::PermissionDenied);
+            }
+            if !self.requests.contains_key(&request_id) {
+                return Err(Error::InvalidRequest);
+            }
+            if self.results.contains_key(&request_id) || self.commitments.contains_key(&request_id) {
+                return Err(Error::DuplicateResult);
+            }
+            self.commitments.insert(request_id, commitment);
+            Ok(())
+        }
+
+        /// Write a committed result, anyone can reveal it. The commitment
+        /// is verified with the hash algorithm of the contract.
+        #[ink(message)]
+        pub fn reveal_result(&mut self, request_id: u64, randint: u32, salt: [u8; 32]) -> Result<(),Error> {
+            let commitment = *self.commitments.get(&request_id).ok_or(Error::InvalidRequest)?;
+            if self.hash_reveal_(randint, &salt) != commitment {
+                return Err(Error::CommitmentMismatch);
+            }
+            self.write_result_(request_id, randint, true)?;
+            self.commitments.take(&request_id);
+            Ok(())
+        }
+
+        /// Hash algorithm the commitments are verified with, one of `HASH_ALG_*`
+        #[ink(message)]
+        pub fn get_hash_alg(&self) -> u8 {
+            self.hash_alg
+        }
 
+        /// Register the public key used to verify relayed results
+        #[ink(message)]
+        pub fn set_owner_pubkey(&mut self, pubkey: [u8; 33]) -> Result<(),Error> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::PermissionDenied);
+            }
+            self.owner_pubkey = pubkey;
+            Ok(())
+        }
+
+        /// Set the result of a request bypassing the range and permission
+        /// checks, so tests can answer requests deterministically.
+        /// Only builds with the `test-helpers` feature accept it, the
+        /// message is always dispatched as ink! can't gate messages on features.
+        #[ink(message)]
+        pub fn force_result(&mut self, request_id: u64, randint: u32) -> Result<(), Error> {
+            if !cfg!(feature = "test-helpers") {
+                return Err(Error::PermissionDenied);
+            }
+
+            let was_pending =
+                self.requests.contains_key(&request_id) && !self.results.contains_key(&request_id);
+            self.results.insert(request_id, (randint, self.env().block_number()));
+            if was_pending {
+                self.pending -= 1;
+            }
+            Ok(())
+        }
+
+        /// Compressed public key of the signer of the `message_hash`,
+        /// the `signature` is `r`, `s` and the recovery id `v` as 0 or 1
+        fn recover_signer_(message_hash: &[u8; 32], signature: &[u8; 65]) -> Option<[u8; 33]> {
+            let mut rs = [0x00; 64];
+            rs.copy_from_slice(&signature[..64]);
+            let recovery_id = secp256k1::RecoveryId::parse(signature[64]).ok()?;
+            secp256k1::recover(
+                &secp256k1::Message::parse(message_hash),
+                &secp256k1::Signature::parse(&rs),
+                &recovery_id,
+            )
+            .ok()
+            .map(|signer| signer.serialize_compressed())
+        }
+
+        fn hash_reveal_(&self, randint: u32, salt: &[u8; 32]) -> Hash {
+            let mut input = [0x00; 36];
+            input[..4].copy_from_slice(&randint.to_le_bytes());
+            input[4..].copy_from_slice(salt);
+            match self.hash_alg {
+                HASH_ALG_KECCAK => Hash::from(self.env().hash_bytes::<ink_env::hash::Keccak256>(&input)),
+                _ => Hash::from(self.env().hash_bytes::<ink_env::hash::Blake2x256>(&input)),
+            }
+        }
+
+        fn write_result_(&mut self, request_id: u64, randint: u32, authorized: bool) -> Result<(),Error> {
             if self.results.contains_key(&request_id) {
                 return Err(Error::DuplicateResult);
             }
 
-            let (min, max) = self.requests.get(&request_id).ok_or(Error::InvalidRequest)?;
+            let (min, max, _) = self.requests.get(&request_id).ok_or(Error::InvalidRequest)?;
             if randint < *min || randint > *max {
                 return Err(Error::InvalidResult);
             }
 
-            if caller == self.owner {
-                self.results.insert(request_id, randint);
+            if authorized {
+                self.results.insert(request_id, (randint, self.env().block_number()));
+                self.pending -= 1;
             } else {
                 return Err(Error::PermissionDenied);
             }
             Ok(())
         }
 
+        /// Remove an unanswered request, only the requester can cancel it
+        #[ink(message)]
+        pub fn cancel_request(&mut self, request_id: u64) -> Result<(),Error> {
+            let caller = self.env().caller();
+
+            let (_, _, requester) = self.requests.get(&request_id).ok_or(Error::InvalidRequest)?;
+            if *requester != caller {
+                return Err(Error::PermissionDenied);
+            }
+            if self.results.contains_key(&request_id) {
+                return Err(Error::InvalidRequest);
+            }
+
+            self.requests.take(&request_id);
+            self.commitments.take(&request_id);
+            self.pending -= 1;
+            self.env().emit_event(RequestCancelled { from: caller, request_id });
+            Ok(())
+        }
+
+        /// Ask for a fresh number in a new range for the same request.
+        /// This intentionally invalidates any previous answer, which
+        /// has to be written again with `write_result`.
+        #[ink(message)]
+        pub fn reroll(&mut self, request_id: u64, new_min: u32, new_max: u32) -> Result<(),Error> {
+            let caller = self.env().caller();
+
+            if new_min > new_max || (new_min == new_max && !self.allow_fixed_range) {
+                return Err(Error::InvalidRange);
+            }
+
+            let (_, _, requester) = *self.requests.get(&request_id).ok_or(Error::InvalidRequest)?;
+            if caller != requester && caller != self.owner {
+                return Err(Error::PermissionDenied);
+            }
+
+            self.requests.insert(request_id, (new_min, new_max, requester));
+            if self.results.take(&request_id).is_some() {
+                self.pending += 1;
+            }
+            // a commitment to the old range can't be revealed anymore
+            self.commitments.take(&request_id);
+            self.env().emit_event(RequestRerolled { from: caller, request_id, min: new_min, max: new_max });
+            Ok(())
+        }
+
+        /// Hand answering over to another account, e.g. a hot worker key
+        #[ink(message)]
+        pub fn set_answerer(&mut self, who: AccountId) -> Result<(),Error> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::PermissionDenied);
+            }
+            self.answerer = who;
+            self.env().emit_event(AnswererChanged { answerer: who });
+            Ok(())
+        }
+
+        /// Change the number of blocks before a written result is final
+        #[ink(message)]
+        pub fn set_confirmations(&mut self, confirmations: u64) -> Result<(),Error> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::PermissionDenied);
+            }
+            self.confirmations = confirmations;
+            Ok(())
+        }
+
+        /// Allow or disallow requests where `min == max`
+        #[ink(message)]
+        pub fn set_allow_fixed_range(&mut self, allow: bool) -> Result<(),Error> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::PermissionDenied);
+            }
+            self.allow_fixed_range = allow;
+            Ok(())
+        }
+
     }
 
     #[cfg(test)]
@@ -105,16 +420,137 @@ mod simple_rng {
         #[ink::test]
         fn it_sets_owner() {
             let owner = AccountId::from([0x0; 32]);
-            let c = SimpleRNG::new(owner);
+            let c = SimpleRNG::new(owner, HASH_ALG_BLAKE2);
             assert_eq!(c.owner, owner);
         }
 
+        #[ink::test]
+        fn it_defaults_unknown_hash_alg() {
+            let owner = AccountId::from([0x0; 32]);
+            assert_eq!(SimpleRNG::new(owner, HASH_ALG_KECCAK).get_hash_alg(), HASH_ALG_KECCAK);
+            assert_eq!(SimpleRNG::new(owner, 7).get_hash_alg(), HASH_ALG_BLAKE2);
+        }
+
         #[ink::test]
         fn it_makes_new_request() {
             let mut c = SimpleRNG::default();
             assert_eq!(c.make_request(0, 100), Ok(1));
             assert_eq!(c.make_request(0, 100), Ok(2));
             assert_eq!(c.make_request(0, 100), Ok(3));
+
+            // ids are never reused
+            c.request_id = u64::MAX;
+            assert_eq!(c.make_request(0, 100), Err(Error::InvalidRequest));
+            assert_eq!(c.pending_count(), 3);
+        }
+
+        #[ink::test]
+        fn it_dedups_keyed_requests() {
+            let accounts = default_accounts();
+            set_next_caller(accounts.alice);
+            let mut c = SimpleRNG::default();
+            assert_eq!(c.make_request_keyed(0, 100, 7), Ok(1));
+            assert_eq!(c.make_request_keyed(0, 100, 7), Ok(1));
+            assert_eq!(c.make_request_keyed(0, 100, 8), Ok(2));
+            assert_eq!(c.pending_count(), 2);
+
+            // the nonce is per caller
+            set_next_caller(accounts.bob);
+            assert_eq!(c.make_request_keyed(0, 100, 7), Ok(3));
+            // rejected requests don't use up the nonce
+            assert_eq!(c.make_request_keyed(100, 0, 9), Err(Error::InvalidRange));
+            assert_eq!(c.make_request_keyed(0, 100, 9), Ok(4));
+        }
+
+        #[ink::test]
+        fn it_rejects_invalid_range() {
+            let mut c = SimpleRNG::default();
+            assert_eq!(c.make_request(100, 0), Err(Error::InvalidRange));
+            assert_eq!(c.make_request(42, 42), Err(Error::InvalidRange));
+
+            assert_eq!(c.set_allow_fixed_range(true), Ok(()));
+            assert_eq!(c.make_request(42, 42), Ok(1));
+            assert_eq!(c.get_range(1), Some((42, 42)));
+            assert_eq!(c.get_range(2), None);
+        }
+
+        #[ink::test]
+        fn it_cancels_request() {
+            let accounts = default_accounts();
+            set_next_caller(accounts.alice);
+            let mut c = SimpleRNG::new(accounts.alice, HASH_ALG_BLAKE2);
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.make_request(0, 100), Ok(2));
+
+            // bob tries to cancel alice's request
+            set_next_caller(accounts.bob);
+            assert_eq!(c.cancel_request(1), Err(Error::PermissionDenied));
+
+            // answered requests can't be cancelled
+            set_next_caller(accounts.alice);
+            assert_eq!(c.write_result(2, 42), Ok(()));
+            assert_eq!(c.cancel_request(2), Err(Error::InvalidRequest));
+
+            assert_eq!(c.cancel_request(1), Ok(()));
+            assert_eq!(c.get_range(1), None);
+            assert_eq!(c.write_result(1, 42), Err(Error::InvalidRequest));
+        }
+
+        #[ink::test]
+        fn it_drops_commitment_on_cancel() {
+            let accounts = default_accounts();
+            set_next_caller(accounts.alice);
+            let mut c = SimpleRNG::new(accounts.alice, HASH_ALG_BLAKE2);
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.commit_result(1, Hash::from([0x01; 32])), Ok(()));
+
+            assert_eq!(c.cancel_request(1), Ok(()));
+            assert!(!c.commitments.contains_key(&1));
+            assert_eq!(c.reveal_result(1, 42, [0x01; 32]), Err(Error::InvalidRequest));
+        }
+
+        #[ink::test]
+        fn it_counts_pending_requests() {
+            let mut c = SimpleRNG::default();
+            assert_eq!(c.pending_count(), 0);
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.make_request(0, 100), Ok(2));
+            assert_eq!(c.make_request(0, 100), Ok(3));
+            assert_eq!(c.pending_count(), 3);
+
+            assert_eq!(c.write_result(1, 42), Ok(()));
+            assert_eq!(c.cancel_request(2), Ok(()));
+            assert_eq!(c.pending_count(), 1);
+        }
+
+        #[ink::test]
+        fn it_rerolls_request() {
+            let mut c = SimpleRNG::default();
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.write_result(1, 42), Ok(()));
+            assert_eq!(c.reroll(1, 10, 5), Err(Error::InvalidRange));
+            assert_eq!(c.reroll(2, 0, 10), Err(Error::InvalidRequest));
+
+            assert_eq!(c.reroll(1, 0, 10), Ok(()));
+            assert_eq!(c.get_result(1), Err(Error::ResultNotFound));
+            assert_eq!(c.get_range(1), Some((0, 10)));
+            assert_eq!(c.pending_count(), 1);
+            assert_eq!(c.write_result(1, 42), Err(Error::InvalidResult));
+            assert_eq!(c.write_result(1, 7), Ok(()));
+        }
+
+        #[ink::test]
+        fn it_drops_commitment_on_reroll() {
+            let accounts = default_accounts();
+            set_next_caller(accounts.alice);
+            let mut c = SimpleRNG::new(accounts.alice, HASH_ALG_BLAKE2);
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.commit_result(1, Hash::from([0x01; 32])), Ok(()));
+
+            // the old commitment can't be revealed and doesn't block a new one
+            assert_eq!(c.reroll(1, 0, 10), Ok(()));
+            assert_eq!(c.reveal_result(1, 42, [0x01; 32]), Err(Error::InvalidRequest));
+            assert_eq!(c.commit_result(1, Hash::from([0x02; 32])), Ok(()));
         }
 
         #[ink::test]
@@ -129,12 +565,99 @@ mod simple_rng {
             assert_eq!(c.get_result(request_id), Ok(result));
         }
 
+        #[ink::test]
+        fn it_returns_request() {
+            let mut c = SimpleRNG::default();
+            assert_eq!(c.get_request(1), None);
+            assert_eq!(c.make_request(10, 100), Ok(1));
+            assert_eq!(c.get_request(1), Some((10, 100, false)));
+            assert_eq!(c.write_result(1, 42), Ok(()));
+            assert_eq!(c.get_request(1), Some((10, 100, true)));
+        }
+
+        #[ink::test]
+        fn it_returns_results_in_bulk() {
+            let mut c = SimpleRNG::default();
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.make_request(0, 100), Ok(2));
+            assert_eq!(c.write_result(2, 42), Ok(()));
+            assert_eq!(c.get_results(vec![2, 1, 3]), vec![Some(42), None, None]);
+        }
+
+        #[ink::test]
+        fn it_waits_for_confirmations() {
+            let mut c = SimpleRNG::default();
+            assert_eq!(c.set_confirmations(2), Ok(()));
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.write_result(1, 42), Ok(()));
+            assert_eq!(c.get_result(1), Err(Error::NotFinalized));
+            assert!(!c.is_final(1));
+
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            assert_eq!(c.get_result(1), Err(Error::NotFinalized));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            assert_eq!(c.get_result(1), Ok(42));
+            assert!(c.is_final(1));
+
+            // never finalized instead of overflowing
+            assert_eq!(c.set_confirmations(u64::MAX), Ok(()));
+            assert_eq!(c.get_result(1), Err(Error::NotFinalized));
+        }
+
+        #[ink::test]
+        fn it_verifies_reveals() {
+            let accounts = default_accounts();
+            set_next_caller(accounts.alice);
+            let mut c = SimpleRNG::new(accounts.alice, HASH_ALG_KECCAK);
+            assert_eq!(c.get_hash_alg(), HASH_ALG_KECCAK);
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.reveal_result(1, 42, [0x01; 32]), Err(Error::InvalidRequest));
+
+            let mut input = [0x01; 36];
+            input[..4].copy_from_slice(&42u32.to_le_bytes());
+            let mut keccak = [0x00; 32];
+            ink_env::hash_bytes::<ink_env::hash::Keccak256>(&input, &mut keccak);
+            let mut blake2 = [0x00; 32];
+            ink_env::hash_bytes::<ink_env::hash::Blake2x256>(&input, &mut blake2);
+
+            // committed with another algorithm
+            assert_eq!(c.commit_result(1, Hash::from(blake2)), Ok(()));
+            assert_eq!(c.reveal_result(1, 42, [0x01; 32]), Err(Error::CommitmentMismatch));
+            assert_eq!(c.get_result(1), Err(Error::ResultNotFound));
+
+            c.commitments.insert(1, Hash::from(keccak));
+            assert_eq!(c.reveal_result(1, 43, [0x01; 32]), Err(Error::CommitmentMismatch));
+            set_next_caller(accounts.bob);
+            assert_eq!(c.reveal_result(1, 42, [0x01; 32]), Ok(()));
+            assert_eq!(c.get_result(1), Ok(42));
+        }
+
+        #[cfg(feature = "test-helpers")]
+        #[ink::test]
+        fn it_forces_result() {
+            let mut c = SimpleRNG::default();
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.force_result(1, 1_000), Ok(()));
+            assert_eq!(c.get_result(1), Ok(1_000));
+            assert_eq!(c.pending_count(), 0);
+        }
+
+        #[cfg(not(feature = "test-helpers"))]
+        #[ink::test]
+        fn it_rejects_forced_result() {
+            let mut c = SimpleRNG::default();
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.force_result(1, 1_000), Err(Error::PermissionDenied));
+            assert_eq!(c.get_result(1), Err(Error::ResultNotFound));
+            assert_eq!(c.pending_count(), 1);
+        }
+
         #[ink::test]
         fn it_rejects_result() {
             // alice is admin
             let accounts = default_accounts();
             set_next_caller(accounts.alice);
-            let mut c = SimpleRNG::new(accounts.alice);
+            let mut c = SimpleRNG::new(accounts.alice, HASH_ALG_BLAKE2);
             assert_eq!(c.owner, accounts.alice);
 
             let result = 42;
@@ -149,6 +672,74 @@ mod simple_rng {
             assert_eq!(c.get_result(request_id), Err(Error::ResultNotFound));
         }
 
+        #[ink::test]
+        fn it_accepts_signed_result() {
+            let accounts = default_accounts();
+            set_next_caller(accounts.alice);
+            let mut c = SimpleRNG::new(accounts.alice, HASH_ALG_BLAKE2);
+            let contract = AccountId::from(DEFAULT_CALLEE_HASH);
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            let signature = sign(&[0x01; 32], contract, 1, 42);
+
+            // no key registered yet
+            assert_eq!(c.write_result_signed(1, 42, signature), Err(Error::PermissionDenied));
+            set_next_caller(accounts.bob);
+            assert_eq!(c.set_owner_pubkey(pubkey(&[0x01; 32])), Err(Error::PermissionDenied));
+            set_next_caller(accounts.alice);
+            assert_eq!(c.set_owner_pubkey(pubkey(&[0x01; 32])), Ok(()));
+
+            // anyone can relay it
+            set_next_caller(accounts.bob);
+            assert_eq!(c.write_result_signed(1, 42, signature), Ok(()));
+            assert_eq!(c.get_result(1), Ok(42));
+        }
+
+        #[ink::test]
+        fn it_rejects_invalid_signature() {
+            let accounts = default_accounts();
+            set_next_caller(accounts.alice);
+            let mut c = SimpleRNG::new(accounts.alice, HASH_ALG_BLAKE2);
+            let contract = AccountId::from(DEFAULT_CALLEE_HASH);
+            assert_eq!(c.set_owner_pubkey(pubkey(&[0x01; 32])), Ok(()));
+            assert_eq!(c.make_request(0, 100), Ok(1));
+
+            // signed by another key
+            let signature = sign(&[0x02; 32], contract, 1, 42);
+            assert_eq!(c.write_result_signed(1, 42, signature), Err(Error::PermissionDenied));
+
+            // signed for another result or request
+            let signature = sign(&[0x01; 32], contract, 1, 42);
+            assert_eq!(c.write_result_signed(1, 43, signature), Err(Error::PermissionDenied));
+            let signature = sign(&[0x01; 32], contract, 2, 42);
+            assert_eq!(c.write_result_signed(1, 42, signature), Err(Error::PermissionDenied));
+
+            // replayed from another contract
+            let signature = sign(&[0x01; 32], accounts.eve, 1, 42);
+            assert_eq!(c.write_result_signed(1, 42, signature), Err(Error::PermissionDenied));
+
+            let mut signature = sign(&[0x01; 32], contract, 1, 42);
+            signature[64] = 4;
+            assert_eq!(c.write_result_signed(1, 42, signature), Err(Error::PermissionDenied));
+            assert_eq!(c.get_result(1), Err(Error::ResultNotFound));
+        }
+
+        #[ink::test]
+        fn it_delegates_answering() {
+            let accounts = default_accounts();
+            set_next_caller(accounts.alice);
+            let mut c = SimpleRNG::new(accounts.alice, HASH_ALG_BLAKE2);
+            assert_eq!(c.make_request(0, 100), Ok(1));
+            assert_eq!(c.make_request(0, 100), Ok(2));
+            assert_eq!(c.set_answerer(accounts.bob), Ok(()));
+
+            // the owner no longer answers
+            assert_eq!(c.write_result(1, 42), Err(Error::PermissionDenied));
+
+            set_next_caller(accounts.bob);
+            assert_eq!(c.write_result(1, 42), Ok(()));
+            assert_eq!(c.set_answerer(accounts.bob), Err(Error::PermissionDenied));
+        }
+
 
         //
         // helper functions
@@ -162,6 +753,27 @@ mod simple_rng {
                 .expect("off-chain environment should have been initialized already")
         }
 
+        fn pubkey(seckey: &[u8; 32]) -> [u8; 33] {
+            let seckey = secp256k1::SecretKey::parse(seckey).expect("Invalid secret key");
+            secp256k1::PublicKey::from_secret_key(&seckey).serialize_compressed()
+        }
+
+        /// Sign the result the way `write_result_signed` expects it
+        fn sign(seckey: &[u8; 32], contract: AccountId, request_id: u64, randint: u32) -> [u8; 65] {
+            let mut message_hash = [0x00; 32];
+            ink_env::hash_encoded::<ink_env::hash::Blake2x256, _>(
+                &(contract, request_id, randint),
+                &mut message_hash,
+            );
+            let seckey = secp256k1::SecretKey::parse(seckey).expect("Invalid secret key");
+            let (signature, recovery_id) =
+                secp256k1::sign(&secp256k1::Message::parse(&message_hash), &seckey);
+            let mut signed = [0x00; 65];
+            signed[..64].copy_from_slice(&signature.serialize());
+            signed[64] = recovery_id.serialize();
+            signed
+        }
+
         fn set_next_caller(caller: AccountId) {
             ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
                 caller,
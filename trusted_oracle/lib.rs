@@ -4,7 +4,49 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod trusted_oracle {
-    use ink_storage::collections::HashMap;
+    use ink_prelude::vec::Vec;
+    use ink_storage::collections::{HashMap, Vec as StorageVec};
+
+    /// Version of the request/callback interface, bumped
+    /// whenever selectors or their arguments change.
+    pub const ABI_VERSION: u32 = 5;
+
+    /// Selector of the `request` message, must match its `#[ink(message)]` attribute.
+    pub const REQUEST_SELECTOR: [u8; 4] = [0xB1, 0x6B, 0x00, 0xB5];
+
+    /// Selector of the `request_with_tip` message, must match its `#[ink(message)]` attribute.
+    pub const REQUEST_WITH_TIP_SELECTOR: [u8; 4] = [0xB1, 0x6B, 0x00, 0xB6];
+
+    /// Selector of the consumer message receiving the oracle results.
+    pub const CALLBACK_SELECTOR: [u8; 4] = [0xB1, 0x6B, 0x00, 0xB5];
+
+    /// Selector of the consumer message receiving the oracle results
+    /// along with the secret revealed by the oracle.
+    pub const SECRET_CALLBACK_SELECTOR: [u8; 4] = [0x5E, 0xC2, 0xE7, 0x00];
+
+    /// Selector of the `cancel_request` message, must match its `#[ink(message)]` attribute.
+    pub const CANCEL_SELECTOR: [u8; 4] = [0xCA, 0x4C, 0xE1, 0x00];
+
+    /// Selector of the `withdraw_refund` message, must match its `#[ink(message)]` attribute.
+    pub const WITHDRAW_REFUND_SELECTOR: [u8; 4] = [0x4E, 0xF0, 0xD0, 0x00];
+
+    /// Selector of the `get_fee` message, must match its `#[ink(message)]` attribute.
+    pub const FEE_SELECTOR: [u8; 4] = [0xFE, 0xE0, 0x00, 0x00];
+
+    /// Number of fee changes kept in the fee history.
+    pub const FEE_HISTORY_LEN: u32 = 32;
+
+    /// Expected kind of result passed to `request_typed`.
+    pub const RESULT_KIND_NUMERIC: u8 = 0;
+    pub const RESULT_KIND_RAW_BYTES: u8 = 1;
+    pub const RESULT_KIND_ANY: u8 = 2;
+
+    /// `CallbackFailed` code of a delivery which failed before the consumer
+    /// could answer, e.g. when the call or the transfer failed.
+    pub const CALL_FAILED_CODE: u8 = u8::MAX;
+
+    /// Number of pending requests `revoke_oracle` refunds right away.
+    pub const REFUND_BATCH_SIZE: u32 = 50;
 
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
     #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
@@ -19,13 +61,39 @@ mod trusted_oracle {
         PaymentRequired,
         CallbackExecutionFailed,
         ValueError,
+        HashMismatch,
+        Cooldown,
+        Paused,
+        StorageFull,
+        InvalidPqlHash,
+        RequestsOutstanding,
+        ResultTypeMismatch,
+        CallbackRejected,
+        QuotaExceeded,
+        ResultPending,
     }
 
-    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
-    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(
+        Debug,
+        Clone,
+        PartialEq,
+        Eq,
+        scale::Encode,
+        scale::Decode,
+        ink_storage::traits::SpreadLayout,
+        ink_storage::traits::PackedLayout,
+    )]
+    #[cfg_attr(
+        feature = "std",
+        derive(::scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
     pub enum OracleResult {
         Numeric(i64),
         RawBytes([u8; 32]),
+        Boolean(bool),
+        Enum(u8),
+        /// The job failed off-chain, carries the error code
+        Error(u8),
     }
 
     #[ink(event)]
@@ -34,10 +102,14 @@ mod trusted_oracle {
         from: AccountId,
         /// PQL ETL Definition
         /// Skip first 2 bytes (hash fn, size) so that we can fit into bytes32
+        #[ink(topic)]
         pql_hash: Hash,
         /// Block number for request expiry
         valid_till: u64,
+        #[ink(topic)]
         request_id: u64,
+        /// Higher priority requests should be processed first
+        priority: u8,
     }
 
     #[ink(event)]
@@ -45,6 +117,25 @@ mod trusted_oracle {
         #[ink(topic)]
         request_id: u64,
         refunded: Balance,
+        /// Part of the fee paid to whoever cleared the expired request
+        cleanup_reward: Balance,
+        /// Taken from the oracle's rewards for missing the deadline
+        penalty: Balance,
+    }
+
+    #[ink(event)]
+    pub struct RefundWithdrawn {
+        #[ink(topic)]
+        user: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct RequestExtended {
+        #[ink(topic)]
+        request_id: u64,
+        /// New block number for request expiry
+        valid_till: u64,
     }
 
     #[ink(event)]
@@ -65,10 +156,66 @@ mod trusted_oracle {
         user: AccountId,
     }
 
+    #[ink(event)]
+    pub struct OracleRevoked {
+        #[ink(topic)]
+        oracle: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct Staked {
+        #[ink(topic)]
+        oracle: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct Slashed {
+        #[ink(topic)]
+        oracle: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct Unstaked {
+        #[ink(topic)]
+        oracle: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct OracleAdded {
+        #[ink(topic)]
+        oracle: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct OracleRemoved {
+        #[ink(topic)]
+        oracle: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct RequestAssigned {
+        #[ink(topic)]
+        request_id: u64,
+        #[ink(topic)]
+        oracle: AccountId,
+    }
+
     #[ink(event)]
     pub struct RewardsClaimed {
         #[ink(topic)]
         oracle: AccountId,
+        /// Payout address the rewards were sent to
+        to: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct TreasuryWithdrawn {
+        #[ink(topic)]
+        treasury: AccountId,
         amount: Balance,
     }
 
@@ -83,10 +230,47 @@ mod trusted_oracle {
     pub struct CallbackComplete {
         #[ink(topic)]
         request_id: u64,
+        #[ink(topic)]
         to: AccountId,
+        /// PQL ETL Definition the request was made with
+        pql_hash: Hash,
         result: OracleResult,
     }
 
+    #[ink(event)]
+    pub struct DeliveryTooLate {
+        #[ink(topic)]
+        request_id: u64,
+        #[ink(topic)]
+        oracle: AccountId,
+        /// Number of blocks past the request's expiry
+        blocks_late: u64,
+    }
+
+    #[ink(event)]
+    pub struct CallbackFailed {
+        #[ink(topic)]
+        request_id: u64,
+        to: AccountId,
+        /// Error code returned by the consumer, or `CALL_FAILED_CODE`
+        code: u8,
+    }
+
+    #[ink(event)]
+    pub struct FallbackToPoll {
+        #[ink(topic)]
+        request_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct Approval {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        spender: AccountId,
+        amount: Balance,
+    }
+
     #[ink(storage)]
     pub struct TrustedOracle {
         /// Admin of the contract
@@ -95,8 +279,8 @@ mod trusted_oracle {
         authorized_users: HashMap<AccountId, ()>,
         /// Who can deliver the results
         authorized_oracle: AccountId,
-        /// Store <RequestId, (AccountId, ExpiryBlock, fee)>
-        requests: HashMap<u64, (AccountId, u64, Balance)>,
+        /// Store <RequestId, (AccountId, ExpiryBlock, fee, priority, pql_hash, CreatedBlock)>
+        requests: HashMap<u64, (AccountId, u64, Balance, u8, Hash, u64)>,
         /// Current request head
         request_idx: u64,
         /// Current fee per request
@@ -105,8 +289,110 @@ mod trusted_oracle {
         min_valid_period: u32,
         /// Maximum period for request timeout
         max_valid_period: u32,
+        /// Period used by `request_default`, between the min and max
+        default_valid_period: u32,
         /// Oracle results for every request
         oracle_results: HashMap<u64, i64>,
+        /// Refunds that could not be transferred right away
+        pending_refunds: HashMap<AccountId, Balance>,
+        /// Share of the fee paid to the oracle in basis points
+        gas_refund_bps: u16,
+        /// Rewards delivered requests earned, not split among the oracles yet
+        accrued_rewards: Balance,
+        /// Receiver of the protocol's share of the fees
+        treasury: AccountId,
+        /// Protocol's share of the fees held for the treasury
+        treasury_balance: Balance,
+        /// Whether identical in-flight requests are merged
+        dedup_requests: bool,
+        /// Pending request id for every in-flight pql hash
+        active_hashes: HashMap<Hash, u64>,
+        /// Additional <RequestId, [(callback, selector, fee, callback_value)]>
+        /// of deduplicated requests
+        subscribers: HashMap<u64, Vec<(AccountId, [u8; 4], Balance, Balance)>>,
+        /// Store <RequestId, DeliveryBlock> of delivered requests
+        delivered: HashMap<u64, u64>,
+        /// Value forwarded to the consumer along with the result
+        callback_values: HashMap<u64, Balance>,
+        /// Funds users deposited to pay the fees of `request_on_behalf`
+        deposits: HashMap<AccountId, Balance>,
+        /// Store <(owner, spender), amount> the spender can draw from the deposit
+        allowances: HashMap<(AccountId, AccountId), Balance>,
+        /// Last `FEE_HISTORY_LEN` fee changes as (block, fee), oldest first
+        fee_history: StorageVec<(u64, Balance)>,
+        /// Number of requests neither delivered nor cleared yet
+        outstanding_requests: u64,
+        /// Oracles the requests are assigned to in round-robin,
+        /// requests are left unassigned while empty
+        oracle_pool: StorageVec<AccountId>,
+        /// Position in the `oracle_pool` of the next assigned oracle
+        next_oracle: u32,
+        /// Store <RequestId, Oracle> the only oracle allowed to deliver the request
+        assigned_oracles: HashMap<u64, AccountId>,
+        /// Number of delivered requests
+        delivered_total: u64,
+        /// Store <Oracle, Deliveries> since the rewards were last split, they are split by them
+        delivered_by: HashMap<AccountId, u64>,
+        /// Number of requests removed after expiring
+        expired_total: u64,
+        /// Number of requests cancelled by the requester
+        cancelled_total: u64,
+        /// Minimum number of blocks between two requests of a user
+        cooldown_blocks: u32,
+        /// Store <AccountId, Block> of the last request of every user
+        last_request: HashMap<AccountId, u64>,
+        /// Number of requests a user can make per `quota_window`, 0 means unlimited
+        quota_count: u32,
+        /// Length of the quota window in blocks
+        quota_window: u32,
+        /// Store <AccountId, (WindowStartBlock, Count)> of the requests in the current window
+        quota_usage: HashMap<AccountId, (u64, u32)>,
+        /// Cut of an expired request's fee paid to the caller of `clear_expired`
+        cleanup_reward: Balance,
+        /// Taken from the oracle's stake and rewards for every request it let expire
+        penalty_per_expiry: Balance,
+        /// Collateral locked by the oracles
+        oracle_stakes: HashMap<AccountId, Balance>,
+        /// Stake required before an oracle can be set
+        min_stake: Balance,
+        /// Store <RequestId, ResultKind> of requests not accepting any result
+        result_kinds: HashMap<u64, u8>,
+        /// Store <RequestId, CallbackAddr> of requests not delivered to the requester
+        callback_addrs: HashMap<u64, AccountId>,
+        /// Store <RequestId, Annotation> metadata passed trough to the off-chain worker
+        annotations: HashMap<u64, [u8; 32]>,
+        /// No new requests are accepted while paused
+        paused: bool,
+        /// Whether the non-essential events (`UserAdded`, `UserRemoved`,
+        /// `FeeChanged`) are emitted. The request lifecycle events
+        /// (`Request`, `CallbackComplete`, `RequestInvalidated`) always are.
+        verbose_events: bool,
+        /// Maximum number of stored requests, 0 means unlimited
+        max_requests: u32,
+        /// Whether only the `allowed_hashes` can be requested
+        restrict_pql_hashes: bool,
+        /// Pre-registered PQL definitions
+        allowed_hashes: HashMap<Hash, ()>,
+        /// Store <PqlHash, Label> human readable names of the registered definitions
+        pql_labels: HashMap<Hash, [u8; 32]>,
+        /// Whether the consumers return `Result<(), Error>` which is inspected,
+        /// otherwise any return value is ignored and only traps fail the delivery
+        callback_returns_result: bool,
+        /// Blocks over which the accrued rewards are released, 0 releases them at once
+        vesting_blocks: u32,
+        /// Store <Oracle, Block> the oracle's rewards were last paid out in
+        last_claim_blocks: HashMap<AccountId, u64>,
+        /// Store <Oracle, Rewards> split off to the oracle and not paid out yet
+        oracle_rewards: HashMap<AccountId, Balance>,
+        /// Failed deliveries before the result is stored for the consumer
+        /// to `collect_result`, 0 never falls back
+        max_delivery_attempts: u32,
+        /// Store <RequestId, Attempts> of the failed deliveries
+        delivery_attempts: HashMap<u64, u32>,
+        /// Store <RequestId, (Oracle, OracleResult)> waiting to be collected by the consumer
+        results: HashMap<u64, (AccountId, OracleResult)>,
+        /// Variant indexes of the `OracleResult`s this deployment delivers
+        supported_kinds: Vec<u8>,
     }
 
     impl TrustedOracle {
@@ -117,6 +403,8 @@ mod trusted_oracle {
             oracle: AccountId,
             min_valid_period: u32,
             max_valid_period: u32,
+            dedup_requests: bool,
+            restrict_pql_hashes: bool,
         ) -> Self {
             Self {
                 admin: admin,
@@ -127,7 +415,55 @@ mod trusted_oracle {
                 fee: (0 as u128).into(),
                 min_valid_period,
                 max_valid_period,
+                default_valid_period: max_valid_period,
                 oracle_results: HashMap::new(),
+                pending_refunds: HashMap::new(),
+                gas_refund_bps: 10_000,
+                accrued_rewards: (0 as u128).into(),
+                treasury: admin,
+                treasury_balance: (0 as u128).into(),
+                dedup_requests,
+                active_hashes: HashMap::new(),
+                subscribers: HashMap::new(),
+                delivered: HashMap::new(),
+                callback_values: HashMap::new(),
+                deposits: HashMap::new(),
+                allowances: HashMap::new(),
+                fee_history: StorageVec::new(),
+                outstanding_requests: 0,
+                oracle_pool: StorageVec::new(),
+                next_oracle: 0,
+                assigned_oracles: HashMap::new(),
+                delivered_total: 0,
+                delivered_by: HashMap::new(),
+                expired_total: 0,
+                cancelled_total: 0,
+                cooldown_blocks: 0,
+                last_request: HashMap::new(),
+                quota_count: 0,
+                quota_window: 0,
+                quota_usage: HashMap::new(),
+                cleanup_reward: (0 as u128).into(),
+                penalty_per_expiry: (0 as u128).into(),
+                oracle_stakes: HashMap::new(),
+                min_stake: (0 as u128).into(),
+                result_kinds: HashMap::new(),
+                callback_addrs: HashMap::new(),
+                annotations: HashMap::new(),
+                paused: false,
+                verbose_events: true,
+                max_requests: 0,
+                restrict_pql_hashes,
+                allowed_hashes: HashMap::new(),
+                pql_labels: HashMap::new(),
+                callback_returns_result: true,
+                vesting_blocks: 0,
+                last_claim_blocks: HashMap::new(),
+                oracle_rewards: HashMap::new(),
+                max_delivery_attempts: 0,
+                delivery_attempts: HashMap::new(),
+                results: HashMap::new(),
+                supported_kinds: ink_prelude::vec![RESULT_KIND_NUMERIC, RESULT_KIND_RAW_BYTES],
             }
         }
 
@@ -146,7 +482,55 @@ mod trusted_oracle {
                 fee: (0 as u128).into(),
                 min_valid_period: 10,
                 max_valid_period: 100,
+                default_valid_period: 100,
                 oracle_results: HashMap::new(),
+                pending_refunds: HashMap::new(),
+                gas_refund_bps: 10_000,
+                accrued_rewards: (0 as u128).into(),
+                treasury: caller,
+                treasury_balance: (0 as u128).into(),
+                dedup_requests: false,
+                active_hashes: HashMap::new(),
+                subscribers: HashMap::new(),
+                delivered: HashMap::new(),
+                callback_values: HashMap::new(),
+                deposits: HashMap::new(),
+                allowances: HashMap::new(),
+                fee_history: StorageVec::new(),
+                outstanding_requests: 0,
+                oracle_pool: StorageVec::new(),
+                next_oracle: 0,
+                assigned_oracles: HashMap::new(),
+                delivered_total: 0,
+                delivered_by: HashMap::new(),
+                expired_total: 0,
+                cancelled_total: 0,
+                cooldown_blocks: 0,
+                last_request: HashMap::new(),
+                quota_count: 0,
+                quota_window: 0,
+                quota_usage: HashMap::new(),
+                cleanup_reward: (0 as u128).into(),
+                penalty_per_expiry: (0 as u128).into(),
+                oracle_stakes: HashMap::new(),
+                min_stake: (0 as u128).into(),
+                result_kinds: HashMap::new(),
+                callback_addrs: HashMap::new(),
+                annotations: HashMap::new(),
+                paused: false,
+                verbose_events: true,
+                max_requests: 0,
+                restrict_pql_hashes: false,
+                allowed_hashes: HashMap::new(),
+                pql_labels: HashMap::new(),
+                callback_returns_result: true,
+                vesting_blocks: 0,
+                last_claim_blocks: HashMap::new(),
+                oracle_rewards: HashMap::new(),
+                max_delivery_attempts: 0,
+                delivery_attempts: HashMap::new(),
+                results: HashMap::new(),
+                supported_kinds: ink_prelude::vec![RESULT_KIND_NUMERIC, RESULT_KIND_RAW_BYTES],
             }
         }
 
@@ -154,141 +538,654 @@ mod trusted_oracle {
         // User Methods
         //
 
-        /// Make a PQL request
+        /// Make a PQL request, the result is delivered to the caller.
+        /// The attached payment has to cover the `fee` and the `callback_value`
+        /// forwarded to the consumer when the result is delivered.
         #[ink(message, payable, selector = "0xB16B00B5")]
-        pub fn request(&mut self, pql_hash: Hash, valid_period: u32) -> Result<u64, Error> {
+        pub fn request(
+            &mut self,
+            pql_hash: Hash,
+            valid_period: u32,
+            priority: u8,
+            callback_value: Balance,
+        ) -> Result<u64, Error> {
+            self.request_typed(pql_hash, valid_period, priority, callback_value, RESULT_KIND_ANY)
+        }
+
+        /// Same as `request`, with a `tip` paid to the oracle on top of the `fee`.
+        /// The attached payment has to cover the `fee`, the `tip` and the
+        /// `callback_value`. The tip is refunded along with the fee.
+        #[ink(message, payable, selector = "0xB16B00B6")]
+        pub fn request_with_tip(
+            &mut self,
+            pql_hash: Hash,
+            valid_period: u32,
+            priority: u8,
+            callback_value: Balance,
+            tip: Balance,
+        ) -> Result<u64, Error> {
             let from = self.env().caller();
+            self.request_(
+                pql_hash,
+                valid_period,
+                priority,
+                callback_value,
+                tip,
+                RESULT_KIND_ANY,
+                from,
+            )
+        }
 
-            if !self.authorized_users.contains_key(&from) {
-                return Err(Error::Unauthorized);
+        /// Same as `request`, but only results of the `expected_result_kind`
+        /// (one of the `RESULT_KIND_*` constants) can be delivered.
+        #[ink(message, payable)]
+        pub fn request_typed(
+            &mut self,
+            pql_hash: Hash,
+            valid_period: u32,
+            priority: u8,
+            callback_value: Balance,
+            expected_result_kind: u8,
+        ) -> Result<u64, Error> {
+            let from = self.env().caller();
+            self.request_(
+                pql_hash,
+                valid_period,
+                priority,
+                callback_value,
+                (0 as u128).into(),
+                expected_result_kind,
+                from,
+            )
+        }
+
+        /// Same as `request`, but the result is delivered to the `callback_addr`.
+        /// The oracle can't deliver it anywhere else.
+        #[ink(message, payable)]
+        pub fn request_with_callback(
+            &mut self,
+            pql_hash: Hash,
+            valid_period: u32,
+            priority: u8,
+            callback_value: Balance,
+            callback_addr: AccountId,
+        ) -> Result<u64, Error> {
+            self.request_(
+                pql_hash,
+                valid_period,
+                priority,
+                callback_value,
+                (0 as u128).into(),
+                RESULT_KIND_ANY,
+                callback_addr,
+            )
+        }
+
+        /// Same as `request`, with a small `annotation` for the off-chain worker,
+        /// e.g. a routing hint. A request deduplicated into an in-flight one
+        /// shares the annotation of the in-flight request.
+        #[ink(message, payable)]
+        pub fn request_annotated(
+            &mut self,
+            pql_hash: Hash,
+            valid_period: u32,
+            priority: u8,
+            callback_value: Balance,
+            annotation: [u8; 32],
+        ) -> Result<u64, Error> {
+            let last_request_idx = self.request_idx;
+            let request_id = self.request(pql_hash, valid_period, priority, callback_value)?;
+            if self.request_idx != last_request_idx {
+                self.annotations.insert(request_id, annotation);
+            }
+            Ok(request_id)
+        }
+
+        fn request_(
+            &mut self,
+            pql_hash: Hash,
+            valid_period: u32,
+            priority: u8,
+            callback_value: Balance,
+            tip: Balance,
+            expected_result_kind: u8,
+            callback_addr: AccountId,
+        ) -> Result<u64, Error> {
+            let from = self.env().caller();
+            let payment = self.env().transferred_balance();
+            let fee = self.fee.checked_add(tip).ok_or(Error::ValueError)?;
+            self.check_request_(from, valid_period, fee, callback_value, payment)?;
+            if expected_result_kind > RESULT_KIND_ANY {
+                return Err(Error::ValueError);
             }
+            // delivering to ourselves would re-enter the oracle
+            if callback_addr == AccountId::from([0x0; 32])
+                || callback_addr == self.env().account_id()
+            {
+                return Err(Error::ValueError);
+            }
+            self.check_pql_hash_(&pql_hash)?;
+            self.make_room_(&pql_hash)?;
+
+            let request_id = self.create_request_(
+                from,
+                pql_hash,
+                valid_period,
+                priority,
+                fee,
+                callback_value,
+                callback_addr,
+            )?;
 
-            if self.fee > (0 as u128).into() {
-                if self.env().transferred_balance() != self.fee {
-                    return Err(Error::PaymentRequired);
+            // nothing to pay for, don't let the value end up with the oracle
+            if fee + callback_value == (0 as u128).into() && payment > (0 as u128).into() {
+                if self.env().transfer(from, payment).is_err() {
+                    self.owe_refund_(from, payment);
                 }
             }
+            if expected_result_kind != RESULT_KIND_ANY {
+                self.result_kinds.insert(request_id, expected_result_kind);
+            }
+            Ok(request_id)
+        }
+
+        /// Same as `request`, valid for the `default_valid_period`
+        /// and without priority or `callback_value`.
+        #[ink(message, payable)]
+        pub fn request_default(&mut self, pql_hash: Hash) -> Result<u64, Error> {
+            self.request(pql_hash, self.default_valid_period, 0, (0 as u128).into())
+        }
+
+        /// Make a PQL request paid from the `owner`'s deposit,
+        /// the caller has to be approved by the `owner` for at least the `fee`.
+        #[ink(message)]
+        pub fn request_on_behalf(
+            &mut self,
+            owner: AccountId,
+            pql_hash: Hash,
+            valid_period: u32,
+        ) -> Result<u64, Error> {
+            let spender = self.env().caller();
+            self.check_request_(owner, valid_period, self.fee, (0 as u128).into(), self.fee)?;
+            self.check_pql_hash_(&pql_hash)?;
+            self.make_room_(&pql_hash)?;
+
+            let allowance = self.allowance(owner, spender);
+            let deposit = self.deposit_of(owner);
+            if allowance < self.fee || deposit < self.fee {
+                return Err(Error::InsufficientFunds);
+            }
+            let request_id = self.create_request_(
+                owner,
+                pql_hash,
+                valid_period,
+                0,
+                self.fee,
+                (0 as u128).into(),
+                owner,
+            )?;
+            self.allowances.insert((owner, spender), allowance - self.fee);
+            self.deposits.insert(owner, deposit - self.fee);
+            Ok(request_id)
+        }
+
+        /// Deposit funds to pay for the requests made on your behalf
+        #[ink(message, payable)]
+        pub fn deposit(&mut self) {
+            let from = self.env().caller();
+            let amount = self.deposit_of(from) + self.env().transferred_balance();
+            self.deposits.insert(from, amount);
+        }
+
+        /// Withdraw the unspent deposit
+        #[ink(message)]
+        pub fn withdraw_deposit(&mut self, amount: Balance) -> Result<(), Error> {
+            let from = self.env().caller();
+            let deposit = self.deposit_of(from);
+            if amount > deposit {
+                return Err(Error::InsufficientFunds);
+            }
+            if self.env().transfer(from, amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+            self.deposits.insert(from, deposit - amount);
+            Ok(())
+        }
+
+        /// Allow the `spender` to pay fees from your deposit up to the `amount`
+        #[ink(message)]
+        pub fn approve(&mut self, spender: AccountId, amount: Balance) {
+            let owner = self.env().caller();
+            self.allowances.insert((owner, spender), amount);
+            self.env().emit_event(Approval { owner, spender, amount });
+        }
+
+        #[ink(message)]
+        pub fn allowance(&self, owner: AccountId, spender: AccountId) -> Balance {
+            *self.allowances.get(&(owner, spender)).unwrap_or(&(0 as u128).into())
+        }
+
+        #[ink(message)]
+        pub fn deposit_of(&self, owner: AccountId) -> Balance {
+            *self.deposits.get(&owner).unwrap_or(&(0 as u128).into())
+        }
+
+        /// Evict the oldest expired request if storing another one would exceed
+        /// `max_requests`. Fails with `StorageFull` if none has expired.
+        fn make_room_(&mut self, pql_hash: &Hash) -> Result<(), Error> {
+            let full = self.max_requests > 0 && self.requests.len() >= self.max_requests;
+            // subscribing to an in-flight request takes no extra slot
+            if !full || self.active_request_(pql_hash).is_some() {
+                return Ok(());
+            }
+
+            let now = self.env().block_number();
+            let oldest_expired = self
+                .requests
+                .iter()
+                .filter(|(_, (_, valid_till, _, _, _, _))| *valid_till < now)
+                .filter(|(request_id, _)| !self.results.contains_key(*request_id))
+                .min_by_key(|(_, (_, _, _, _, _, created_at))| *created_at)
+                .map(|(request_id, (user_id, _, fee, _, _, _))| (*request_id, *user_id, *fee));
+            let (request_id, user_id, fee) = oldest_expired.ok_or(Error::StorageFull)?;
 
-            // loop around to 0 after u64::max_value() is reached
-            self.request_idx = self.request_idx.wrapping_add(1);
+            let callback_value = self.callback_value_(request_id);
+            self.refund_(request_id, user_id, fee + callback_value)?;
+            self.refund_subscribers_(request_id)?;
+            self.remove_request_(request_id);
+            self.expired_total += 1;
+            Ok(())
+        }
+
+        /// Store the request, or subscribe to an identical in-flight one.
+        /// Subscribers are delivered to the requester, so requests bound
+        /// to another `callback_addr` are never deduplicated.
+        fn create_request_(
+            &mut self,
+            from: AccountId,
+            pql_hash: Hash,
+            valid_period: u32,
+            priority: u8,
+            fee: Balance,
+            callback_value: Balance,
+            callback_addr: AccountId,
+        ) -> Result<u64, Error> {
+            let created_at = self.env().block_number();
+            let valid_till = mathutil::checked_add_block(created_at, valid_period as u64)
+                .ok_or(Error::ValueError)?;
+            let request_id = mathutil::checked_next_id(self.request_idx).ok_or(Error::ValueError)?;
+            self.last_request.insert(from, created_at);
+            if self.quota_count > 0 {
+                let used = self.quota_used_(from);
+                let window_start = match self.quota_usage.get(&from) {
+                    Some((window_start, _)) if used > 0 => *window_start,
+                    _ => created_at,
+                };
+                self.quota_usage.insert(from, (window_start, used + 1));
+            }
 
-            // TODO: until block_number() is fixed, we use `valid_period`
-            // as the last valid block_id,
-            // therefore the last block should be computed off-chain
+            // the same job is already in-flight, subscribe to its result instead
+            let active_request = self.active_request_(&pql_hash).filter(|_| callback_addr == from);
+            if let Some(request_id) = active_request {
+                let mut subscribers = self.subscribers.take(&request_id).unwrap_or_default();
+                subscribers.push((from, CALLBACK_SELECTOR, fee, callback_value));
+                self.subscribers.insert(request_id, subscribers);
+                return Ok(request_id);
+            }
 
-            //// require some reasonable valid_period
-            //if valid_period < self.min_valid_period || valid_period > self.max_valid_period {
-            //return Err(Error::ValueError);
-            //}
-            //let valid_till = self.env().block_number() + valid_period as u64;
-            //self.requests
-            //.insert(self.request_idx, (from, valid_till, self.fee));
+            // ids are never reused, so they can't loop around
+            self.request_idx = request_id;
 
-            self.requests
-                .insert(self.request_idx, (from, valid_period as u64, self.fee));
+            self.outstanding_requests += 1;
+            self.requests.insert(
+                self.request_idx,
+                (from, valid_till, fee, priority, pql_hash, created_at),
+            );
+            if self.dedup_requests {
+                self.active_hashes.insert(pql_hash, self.request_idx);
+            }
+            if callback_value > (0 as u128).into() {
+                self.callback_values.insert(self.request_idx, callback_value);
+            }
+            if callback_addr != from {
+                self.callback_addrs.insert(self.request_idx, callback_addr);
+            }
             self.env().emit_event(Request {
                 from,
                 pql_hash,
-                valid_till: valid_period as u64,
+                valid_till,
                 request_id: self.request_idx,
+                priority,
             });
+            if let Some(oracle) = self.next_oracle_() {
+                self.assign_oracle_(self.request_idx, oracle);
+            }
             Ok(self.request_idx)
         }
 
+        /// Check if a request with the given parameters and attached
+        /// `payment` would be accepted, without making the request.
+        /// Returns the same error as `request` would.
+        #[ink(message)]
+        pub fn validate_request(
+            &self,
+            valid_period: u32,
+            callback_value: Balance,
+            payment: Balance,
+        ) -> Result<(), Error> {
+            let from = self.env().caller();
+            self.check_request_(from, valid_period, self.fee, callback_value, payment)
+        }
+
+        /// Cancel a pending request, the fee and `callback_value` are refunded.
+        /// Only the requester can cancel.
+        #[ink(message, selector = "0xCA4CE100")]
+        pub fn cancel_request(&mut self, request_id: u64) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            let (user_id, _, fee, _, _, _) =
+                *self.requests.get(&request_id).ok_or(Error::RequestNotFound)?;
+            if from != user_id {
+                return Err(Error::Unauthorized);
+            }
+            // the result is waiting to be collected
+            if self.results.contains_key(&request_id) {
+                return Err(Error::ResultPending);
+            }
+
+            let callback_value = self.callback_value_(request_id);
+            self.refund_(request_id, user_id, fee + callback_value)?;
+            self.refund_subscribers_(request_id)?;
+            self.remove_request_(request_id);
+            self.cancelled_total += 1;
+            Ok(())
+        }
+
         //
         // Oracle Methods
         //
 
-        /// Deliver the oracle result
+        /// Deliver the oracle result.
+        /// The `pql_hash` has to match the one the request was made with.
+        /// The result goes to the address bound at request time,
+        /// the `callback_addr` supplied by the oracle is ignored.
         #[ink(message)]
         pub fn callback(
             &mut self,
             request_id: u64,
-            callback_addr: AccountId,
+            pql_hash: Hash,
+            _callback_addr: AccountId,
             result: OracleResult,
         ) -> Result<(), Error> {
-            let from = self.env().caller();
-
-            if from != self.authorized_oracle {
-                return Err(Error::Unauthorized);
-            }
+            self.callback_(request_id, pql_hash, result, None)
+        }
 
-            // check if request_id has expired
-            if let Some(request) = self.requests.get(&request_id) {
-                let (user_id, valid_till, fee) = request;
-                if *valid_till < self.env().block_number() {
-                    self.refund_(request_id, *user_id, *fee)?;
-                    self.requests.take(&request_id);
-                    return Err(Error::RequestExpired);
-                }
-            } else {
-                return Err(Error::RequestNotFound);
-            }
+        /// Same as `callback`, but also reveals the oracle's `secret` and
+        /// commits to the next one, for consumers which only accept results
+        /// along with a secret. The result is delivered to the consumer's
+        /// `SECRET_CALLBACK_SELECTOR` message.
+        #[ink(message)]
+        pub fn callback_with_secret(
+            &mut self,
+            request_id: u64,
+            pql_hash: Hash,
+            _callback_addr: AccountId,
+            result: OracleResult,
+            secret: [u8; 32],
+            next_secret_hash: Hash,
+        ) -> Result<(), Error> {
+            self.callback_(request_id, pql_hash, result, Some((secret, next_secret_hash)))
+        }
 
-            // deliver result as callback
-            // note: this will not work off-chain, see:
-            // https://paritytech.github.io/ink/src/ink_env/call/call_builder.rs.html#53
+        fn callback_(
+            &mut self,
+            request_id: u64,
+            pql_hash: Hash,
+            result: OracleResult,
+            secret: Option<([u8; 32], Hash)>,
+        ) -> Result<(), Error> {
+            let from = self.env().caller();
 
-            // // method 1:
-            // // https://paritytech.github.io/ink/ink_env/fn.invoke_contract.html
-            //
-            // use ink_env::call::{
-            //     utils::{ReturnType},
-            //     Selector, ExecutionInput, CallParams};
-            // let selector = Selector::new([
-            //     0xB1, 0x6B, 0x00, 0xB5,
-            // ]);
-            // let calldata: CallParams<ink_env::DefaultEnvironment, _, ()> = CallParams{
-            //     /// smart contract we are calling
-            //     callee: callback_addr,
-            //     /// Default gas limit
-            //     gas_limit: 1_000_000 as u64,
-            //     /// Not sending any funds
-            //     transferred_value: (0 as u128).into(),
-            //     /// Not expecting a return type
-            //     return_type: ReturnType::default(),
-            //     /// Function and its args??
-            //     exec_input: ExecutionInput::new(selector).push_arg(42)
-            // };
-            // if let Err(err) = ink_env::invoke_contract(&calldata) {
-            //     return Err(Error::CallbackExecutionFailed);
-            // }
+            let (user_id, _) = self.check_delivery_(from, request_id, pql_hash)?;
+            self.check_result_kind_(request_id, &result)?;
+            let callback_addr = self.callback_addr_(request_id, user_id);
 
-            // method 2:
-            // https://paritytech.github.io/ink/ink_env/call/fn.build_call.html
-            //
-            use ink_env::call::{build_call, ExecutionInput, Selector};
-            let selector = Selector::new([0xB1, 0x6B, 0x00, 0xB5]);
-            let callback = build_call::<ink_env::DefaultEnvironment>()
-                .callee(callback_addr)
-                .gas_limit(1_000_000)
-                .transferred_value(0)
-                .exec_input(ExecutionInput::new(selector).push_arg(&result))
-                .returns::<()>()
-                .fire();
-            if let Err(_) = callback {
-                return Err(Error::CallbackExecutionFailed);
+            // deliver result as callback, the fee stays in escrow
+            // until the consumer accepts the result
+            let callback_value = self.callback_value_(request_id);
+            let selector = match secret {
+                Some(_) => SECRET_CALLBACK_SELECTOR,
+                None => CALLBACK_SELECTOR,
+            };
+            let delivery = self.deliver_(
+                request_id,
+                callback_addr,
+                selector,
+                callback_value,
+                &result,
+                secret,
+            );
+            if let Err(err) = delivery {
+                if err == Error::CallbackRejected {
+                    self.reject_delivery_(request_id)?;
+                }
+                if err == Error::CallbackExecutionFailed
+                    && self.fall_back_to_poll_(request_id, from, &result)
+                {
+                    return Ok(());
+                }
+                return Err(err);
             }
 
             // TODO
-            // There are a few issues with this implementation
-            // 1. The callback might not be the same as in PQL.
-            // Should the user define the callback in a request instead?
-            // 2. Can we do better than responding with raw bytes?
+            // Can we do better than responding with raw bytes?
             // Perhaps we could do some decoding here?
-            // 3. Should we expect an Ok(()) response from the callee?
 
-            // remove request from storage
-            self.requests.take(&request_id);
+            // remove request from storage and pay for the delivery
+            if let Some((_, _, fee, _, _, _)) = self.remove_request_(request_id) {
+                self.split_fee_(fee)?;
+            }
+            self.delivered.insert(request_id, self.env().block_number());
+            self.record_delivery_(from);
             let event = CallbackComplete {
                 request_id,
                 to: callback_addr,
-                result,
+                pql_hash,
+                result: result.clone(),
             };
             self.env().emit_event(event);
+
+            // fan the result out to the deduplicated requests,
+            // subscribers which fail to accept it are refunded
+            let subscribers = self.subscribers.take(&request_id).unwrap_or_default();
+            for (to, selector, fee, callback_value) in subscribers {
+                if self
+                    .deliver_(request_id, to, selector, callback_value, &result, None)
+                    .is_ok()
+                {
+                    self.split_fee_(fee)?;
+                    self.env().emit_event(CallbackComplete {
+                        request_id,
+                        to,
+                        pql_hash,
+                        result: result.clone(),
+                    });
+                } else {
+                    self.refund_(request_id, to, fee + callback_value)?;
+                }
+            }
+            Ok(())
+        }
+
+        /// Deliver several results in one call. Every delivery goes
+        /// through the same checks as `callback`, using the job hash
+        /// stored with the request. A failed delivery doesn't abort the
+        /// batch, its flag in the returned list is just set to `false`.
+        #[ink(message)]
+        pub fn callback_batch(
+            &mut self,
+            deliveries: Vec<(u64, AccountId, OracleResult)>,
+        ) -> Result<Vec<bool>, Error> {
+            let from = self.env().caller();
+
+            if self.revoked_()
+                || (from != self.authorized_oracle && !self.oracle_pool.iter().any(|o| *o == from))
+            {
+                return Err(Error::Unauthorized);
+            }
+
+            let mut delivered = Vec::new();
+            for (request_id, callback_addr, result) in deliveries {
+                let pql_hash = match self.requests.get(&request_id) {
+                    Some((_, _, _, _, pql_hash, _)) => *pql_hash,
+                    None => {
+                        delivered.push(false);
+                        continue;
+                    }
+                };
+                delivered.push(
+                    self.callback(request_id, pql_hash, callback_addr, result)
+                        .is_ok(),
+                );
+            }
+            Ok(delivered)
+        }
+
+        /// Let the consumer know the job failed off-chain.
+        /// `OracleResult::Error(code)` is delivered instead of the result
+        /// and the requester is refunded since no data was produced.
+        /// Like `callback`, the `pql_hash` has to match the request's
+        /// and the `callback_addr` supplied by the oracle is ignored.
+        #[ink(message)]
+        pub fn callback_error(
+            &mut self,
+            request_id: u64,
+            pql_hash: Hash,
+            _callback_addr: AccountId,
+            code: u8,
+        ) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            let (user_id, fee) = self.check_delivery_(from, request_id, pql_hash)?;
+            let callback_addr = self.callback_addr_(request_id, user_id);
+            let result = OracleResult::Error(code);
+            self.deliver_(
+                request_id,
+                callback_addr,
+                CALLBACK_SELECTOR,
+                (0 as u128).into(),
+                &result,
+                None,
+            )?;
+
+            let callback_value = self.callback_value_(request_id);
+            self.refund_(request_id, user_id, fee + callback_value)?;
+            self.env().emit_event(CallbackComplete {
+                request_id,
+                to: callback_addr,
+                pql_hash,
+                result: result.clone(),
+            });
+
+            // the subscribers are told as well and refunded
+            let subscribers = self.subscribers.take(&request_id).unwrap_or_default();
+            for (to, selector, fee, callback_value) in subscribers {
+                let _ = self.deliver_(request_id, to, selector, (0 as u128).into(), &result, None);
+                self.refund_(request_id, to, fee + callback_value)?;
+            }
+            self.remove_request_(request_id);
             Ok(())
         }
 
+        /// Collect the result stored after the push delivery kept failing,
+        /// only the consumer of the request can collect it.
+        #[ink(message)]
+        pub fn collect_result(&mut self, request_id: u64) -> Result<OracleResult, Error> {
+            let from = self.env().caller();
+
+            let (user_id, _, _, _, pql_hash, _) =
+                *self.requests.get(&request_id).ok_or(Error::RequestNotFound)?;
+            if from != self.callback_addr_(request_id, user_id) {
+                return Err(Error::Unauthorized);
+            }
+            let (oracle, result) = self.results.take(&request_id).ok_or(Error::RequestNotFound)?;
+
+            // the held fee is paid to the oracle now, and the
+            // callback value goes to the consumer
+            let callback_value = self.callback_value_(request_id);
+            self.record_delivery_(oracle);
+            if let Some((_, _, fee, _, _, _)) = self.remove_request_(request_id) {
+                self.split_fee_(fee)?;
+            }
+            self.pay_(from, callback_value)?;
+            self.refund_subscribers_(request_id)?;
+            self.delivered.insert(request_id, self.env().block_number());
+            self.env().emit_event(CallbackComplete {
+                request_id,
+                to: from,
+                pql_hash,
+                result: result.clone(),
+            });
+            Ok(result)
+        }
+
+        /// Ids of the unexpired requests with at least `min_priority`,
+        /// the highest priority first and the oldest first among equals.
+        #[ink(message)]
+        pub fn list_requests_by_priority(&self, min_priority: u8, limit: u32) -> Vec<u64> {
+            let now = self.env().block_number();
+            let mut pending: Vec<(u64, u8)> = self
+                .requests
+                .iter()
+                .filter(|(_, (_, valid_till, _, priority, _, _))| {
+                    *priority >= min_priority && *valid_till >= now
+                })
+                .map(|(request_id, (_, _, _, priority, _, _))| (*request_id, *priority))
+                .collect();
+            pending.sort_unstable_by_key(|(request_id, priority)| {
+                (core::cmp::Reverse(*priority), *request_id)
+            });
+            pending.truncate(limit as usize);
+            pending.into_iter().map(|(request_id, _)| request_id).collect()
+        }
+
+        /// Unexpired requests with an id of at least `start`, ordered by id, as
+        /// `(request_id, requester, pql_hash, valid_till, fee)`.
+        /// Resume polling from the last returned id + 1.
+        #[ink(message)]
+        pub fn list_pending(
+            &self,
+            start: u64,
+            limit: u32,
+        ) -> Vec<(u64, AccountId, Hash, u64, Balance)> {
+            let now = self.env().block_number();
+            let mut pending: Vec<(u64, AccountId, Hash, u64, Balance)> = self
+                .requests
+                .iter()
+                .filter(|(request_id, (_, valid_till, _, _, _, _))| {
+                    **request_id >= start && *valid_till >= now
+                })
+                .map(|(request_id, (from, valid_till, fee, _, pql_hash, _))| {
+                    (*request_id, *from, *pql_hash, *valid_till, *fee)
+                })
+                .collect();
+            pending.sort_unstable_by_key(|(request_id, _, _, _, _)| *request_id);
+            pending.truncate(limit as usize);
+            pending
+        }
+
+        /// Annotation the request was made with
+        #[ink(message)]
+        pub fn annotation_of(&self, request_id: u64) -> Option<[u8; 32]> {
+            self.annotations.get(&request_id).copied()
+        }
+
+        /// Whether the result of the request has been delivered
+        #[ink(message)]
+        pub fn was_delivered(&self, request_id: u64) -> bool {
+            self.delivered.contains_key(&request_id)
+        }
+
         /// Simpler callback that sets the variable in the contract.
         #[ink(message)]
         pub fn simple_callback(
@@ -299,7 +1196,7 @@ mod trusted_oracle {
         ) -> Result<(), Error> {
             let from = self.env().caller();
 
-            if from != self.authorized_oracle {
+            if !self.is_oracle_(from, request_id) {
                 return Err(Error::Unauthorized);
             }
 
@@ -307,17 +1204,43 @@ mod trusted_oracle {
             if self.oracle_results.contains_key(&request_id) {
                 return Err(Error::RequestExpired);
             }
+            if !self.requests.contains_key(&request_id) {
+                return Err(Error::RequestNotFound);
+            }
+            self.check_result_kind_(request_id, &result)?;
 
             // Write the result to result HashMap
             if let OracleResult::Numeric(value) = result {
                 self.oracle_results.insert(request_id, value);
             }
 
-            // remove request from storage
-            self.requests.take(&request_id);
+            // remove request from storage and pay for the delivery,
+            // subscribers can read the stored result as well.
+            // Nothing is forwarded to the consumers, so the callback
+            // values are owed back to the requesters.
+            let callback_value = self.callback_value_(request_id);
+            let mut pql_hash = Hash::from([0x00; 32]);
+            let mut to = callback_addr;
+            if let Some((user_id, _, _, _, _, _)) = self.requests.get(&request_id) {
+                to = self.callback_addr_(request_id, *user_id);
+            }
+            if let Some((user_id, _, fee, _, hash, _)) = self.remove_request_(request_id) {
+                self.split_fee_(fee)?;
+                self.owe_refund_(user_id, callback_value);
+                pql_hash = hash;
+            }
+            self.delivered.insert(request_id, self.env().block_number());
+            self.record_delivery_(from);
+            for (user_id, _, fee, callback_value) in
+                self.subscribers.take(&request_id).unwrap_or_default()
+            {
+                self.split_fee_(fee)?;
+                self.owe_refund_(user_id, callback_value);
+            }
             let event = CallbackComplete {
                 request_id,
-                to: callback_addr,
+                to,
+                pql_hash,
                 result,
             };
             self.env().emit_event(event);
@@ -333,6 +1256,92 @@ mod trusted_oracle {
             }
         }
 
+        /// Whether refunding the request would keep the contract
+        /// above the chain's existential deposit.
+        #[ink(message)]
+        pub fn can_refund(&self, request_id: u64) -> bool {
+            let fee = match self.requests.get(&request_id) {
+                Some((_, _, fee, _, _, _)) => *fee + self.callback_value_(request_id),
+                None => return false,
+            };
+            let balance = self.env().balance();
+            balance >= fee && balance - fee >= self.env().minimum_balance()
+        }
+
+        /// Amount of refunds owed to the user
+        #[ink(message)]
+        pub fn pending_refund(&self, user: AccountId) -> Balance {
+            *self.pending_refunds.get(&user).unwrap_or(&0)
+        }
+
+        /// Withdraw the refunds which could not be transferred
+        /// at the time of the request invalidation.
+        #[ink(message, selector = "0x4EF0D000")]
+        pub fn withdraw_refund(&mut self) -> Result<Balance, Error> {
+            let from = self.env().caller();
+
+            let amount = match self.pending_refunds.take(&from) {
+                Some(amount) => amount,
+                None => return Ok(0),
+            };
+            if let Err(err) = self.env().transfer(from, amount) {
+                // keep the refund owed until the contract can afford it
+                self.pending_refunds.insert(from, amount);
+                return match err {
+                    ink_env::Error::BelowSubsistenceThreshold => {
+                        Err(Error::BelowSubsistenceThreshold)
+                    }
+                    _ => Err(Error::TransferFailed),
+                };
+            }
+            self.env().emit_event(RefundWithdrawn { user: from, amount });
+            Ok(amount)
+        }
+
+        /// Lock collateral, required before becoming the oracle.
+        #[ink(message, payable)]
+        pub fn stake(&mut self) {
+            let oracle = self.env().caller();
+            let amount = self.env().transferred_balance();
+            self.oracle_stakes.insert(oracle, self.stake_of(oracle) + amount);
+            self.env().emit_event(Staked { oracle, amount });
+        }
+
+        /// Withdraw the whole stake. The current oracle can only unstake
+        /// with no outstanding requests, the pool oracles with no undelivered
+        /// requests assigned to them.
+        #[ink(message)]
+        pub fn unstake(&mut self) -> Result<Balance, Error> {
+            let oracle = self.env().caller();
+
+            if oracle == self.authorized_oracle && self.outstanding_requests > 0 {
+                return Err(Error::RequestsOutstanding);
+            }
+            let assigned = self
+                .assigned_oracles
+                .iter()
+                .any(|(request_id, o)| *o == oracle && !self.results.contains_key(request_id));
+            if assigned {
+                return Err(Error::RequestsOutstanding);
+            }
+
+            let amount = match self.oracle_stakes.take(&oracle) {
+                Some(amount) => amount,
+                None => return Ok((0 as u128).into()),
+            };
+            if self.env().transfer(oracle, amount).is_err() {
+                self.oracle_stakes.insert(oracle, amount);
+                return Err(Error::TransferFailed);
+            }
+            self.env().emit_event(Unstaked { oracle, amount });
+            Ok(amount)
+        }
+
+        #[ink(message)]
+        pub fn stake_of(&self, oracle: AccountId) -> Balance {
+            *self.oracle_stakes.get(&oracle).unwrap_or(&(0 as u128).into())
+        }
+
         /// Distribute the rewards to the oracle.
         #[ink(message)]
         pub fn claim_rewards(&mut self) -> Result<(), Error> {
@@ -346,6 +1355,42 @@ mod trusted_oracle {
             self.claim_()
         }
 
+        /// Requests delivered by the oracle since the rewards were last split.
+        #[ink(message)]
+        pub fn deliveries_by(&self, oracle: AccountId) -> u64 {
+            self.delivered_by.get(&oracle).copied().unwrap_or(0)
+        }
+
+        /// Rewards earned by the oracles and not paid out yet.
+        #[ink(message)]
+        pub fn pending_rewards(&self) -> Balance {
+            self.oracle_rewards
+                .iter()
+                .fold(self.accrued_rewards, |total, (_, rewards)| total + *rewards)
+        }
+
+        /// Rewards released to the oracle by its vesting schedule so far.
+        #[ink(message)]
+        pub fn releasable_rewards(&self, oracle: AccountId) -> Balance {
+            self.released_(oracle, self.rewards_of_(oracle))
+        }
+
+        /// Distribute the rewards to a separate payout address.
+        #[ink(message)]
+        pub fn claim_rewards_to(&mut self, to: AccountId) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.authorized_oracle {
+                return Err(Error::Unauthorized);
+            }
+
+            if to == AccountId::from([0x0; 32]) {
+                return Err(Error::ValueError);
+            }
+
+            self.claim_to_(to)
+        }
+
         //
         // Admin methods
         //
@@ -359,6 +1404,10 @@ mod trusted_oracle {
                 return Err(Error::Unauthorized);
             }
 
+            if self.stake_of(new_oracle) < self.min_stake {
+                return Err(Error::InsufficientFunds);
+            }
+
             // send rewards to the current oracle
             self.claim_()?;
 
@@ -379,98 +1428,171 @@ mod trusted_oracle {
 
             let old_fee = self.fee.clone();
             self.fee = new_fee;
-            self.env().emit_event(FeeChanged { old_fee, new_fee });
+            self.record_fee_(new_fee);
+            if self.verbose_events {
+                self.env().emit_event(FeeChanged { old_fee, new_fee });
+            }
             Ok(())
         }
 
-        /// Add user to the oracle contract
+        /// Change the share of the fee paid to the oracle on delivery,
+        /// the remainder is held for the treasury.
         #[ink(message)]
-        pub fn add_user(&mut self, user: AccountId) -> Result<(), Error> {
+        pub fn set_gas_refund_bps(&mut self, gas_refund_bps: u16) -> Result<(), Error> {
             let from = self.env().caller();
 
             if from != self.admin {
                 return Err(Error::Unauthorized);
             }
 
-            // add the user
-            self.authorized_users.insert(user.clone(), ());
-            self.env().emit_event(UserAdded { user });
+            if gas_refund_bps > 10_000 {
+                return Err(Error::ValueError);
+            }
+            self.gas_refund_bps = gas_refund_bps;
             Ok(())
         }
 
-        /// Remove user from the oracle contract
+        /// Change the cut of an expired request's fee paid for clearing it.
         #[ink(message)]
-        pub fn remove_user(&mut self, user: AccountId) -> Result<(), Error> {
+        pub fn set_cleanup_reward(&mut self, cleanup_reward: Balance) -> Result<(), Error> {
             let from = self.env().caller();
 
             if from != self.admin {
                 return Err(Error::Unauthorized);
             }
 
-            // remove the user
-            self.authorized_users.take(&user);
-            self.env().emit_event(UserRemoved { user });
+            self.cleanup_reward = cleanup_reward;
             Ok(())
         }
 
-        /// Remove expired request to free contract storage
+        /// Change the stake required from a new oracle.
         #[ink(message)]
-        pub fn clear_expired(&mut self, request_id: u64) -> Result<(), Error> {
-            if let Some(request) = self.requests.get(&request_id) {
-                let (user_id, valid_till, fee) = request;
-                if *valid_till < self.env().block_number() {
-                    self.refund_(request_id, *user_id, *fee)?;
-                    self.requests.take(&request_id);
-                    return Ok(());
-                } else {
-                    return Err(Error::RequestNotExpired);
-                }
-            } else {
-                return Err(Error::RequestNotFound);
+        pub fn set_min_stake(&mut self, min_stake: Balance) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
             }
+
+            self.min_stake = min_stake;
+            Ok(())
         }
 
-        //
-        // Other
-        //
+        /// Change the penalty for letting a request expire.
+        #[ink(message)]
+        pub fn set_penalty_per_expiry(&mut self, penalty_per_expiry: Balance) -> Result<(), Error> {
+            let from = self.env().caller();
 
-        // TODO: check if this is private & internal only
-        fn claim_(&mut self) -> Result<(), Error> {
-            let balance = self.env().balance();
-            if balance > (0 as u128).into() {
-                let tx = self.env().transfer(self.authorized_oracle, balance);
-                return match tx {
-                    Ok(_) => {
-                        let event = RewardsClaimed {
-                            oracle: self.authorized_oracle,
-                            amount: balance,
-                        };
-                        self.env().emit_event(event);
-                        Ok(())
-                    }
-                    Err(err) => match err {
-                        ink_env::Error::BelowSubsistenceThreshold => {
-                            Err(Error::BelowSubsistenceThreshold)
-                        }
-                        _ => Err(Error::TransferFailed),
-                    },
-                };
+            if from != self.admin {
+                return Err(Error::Unauthorized);
             }
+
+            self.penalty_per_expiry = penalty_per_expiry;
             Ok(())
         }
 
-        // TODO: check if this is private & internal only
-        fn refund_(
+        /// Change the minimum number of blocks between two requests of a user.
+        #[ink(message)]
+        pub fn set_cooldown_blocks(&mut self, cooldown_blocks: u32) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.cooldown_blocks = cooldown_blocks;
+            Ok(())
+        }
+
+        /// Change the period `request_default` is valid for,
+        /// has to be within the valid period bounds.
+        #[ink(message)]
+        pub fn set_default_valid_period(&mut self, default_valid_period: u32) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            if default_valid_period < self.min_valid_period
+                || default_valid_period > self.max_valid_period
+            {
+                return Err(Error::ValueError);
+            }
+            self.default_valid_period = default_valid_period;
+            Ok(())
+        }
+
+        /// Change the bounds of the request valid period,
+        /// they have to include the `default_valid_period`.
+        #[ink(message)]
+        pub fn set_valid_period_bounds(
             &mut self,
-            request_id: u64,
-            user_id: AccountId,
-            fee: Balance,
+            min_valid_period: u32,
+            max_valid_period: u32,
         ) -> Result<(), Error> {
-            if fee > (0 as u128).into() {
-                if self.env().balance() < fee {
-                    return Err(Error::InsufficientFunds);
-                }
-                if let Err(err) = self.env().transfer(user_id, fee) {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            if min_valid_period > max_valid_period
+                || self.default_valid_period < min_valid_period
+                || self.default_valid_period > max_valid_period
+            {
+                return Err(Error::ValueError);
+            }
+            self.min_valid_period = min_valid_period;
+            self.max_valid_period = max_valid_period;
+            Ok(())
+        }
+
+        /// Limit the users to `quota_count` requests per `quota_window` blocks,
+        /// a `quota_count` of 0 means unlimited.
+        #[ink(message)]
+        pub fn set_quota(&mut self, quota_count: u32, quota_window: u32) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            // an empty window would never reset the quota
+            if quota_count > 0 && quota_window == 0 {
+                return Err(Error::ValueError);
+            }
+
+            self.quota_count = quota_count;
+            self.quota_window = quota_window;
+            Ok(())
+        }
+
+        /// Change the receiver of the protocol's share of the fees.
+        #[ink(message)]
+        pub fn set_treasury(&mut self, treasury: AccountId) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.treasury = treasury;
+            Ok(())
+        }
+
+        /// Send the protocol's share of the fees to the treasury.
+        #[ink(message)]
+        pub fn withdraw_treasury(&mut self) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            let amount = self.treasury_balance;
+            if amount > (0 as u128).into() {
+                if let Err(err) = self.env().transfer(self.treasury, amount) {
                     return match err {
                         ink_env::Error::BelowSubsistenceThreshold => {
                             Err(Error::BelowSubsistenceThreshold)
@@ -478,131 +1600,2671 @@ mod trusted_oracle {
                         _ => Err(Error::TransferFailed),
                     };
                 }
+                self.treasury_balance = (0 as u128).into();
+                self.env().emit_event(TreasuryWithdrawn {
+                    treasury: self.treasury,
+                    amount,
+                });
             }
-            let event = RequestInvalidated {
-                request_id,
-                refunded: fee,
-            };
-            self.env().emit_event(event);
             Ok(())
         }
-    }
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
-        use ink_lang as ink;
+        /// Add user to the oracle contract
+        #[ink(message)]
+        pub fn add_user(&mut self, user: AccountId) -> Result<(), Error> {
+            let from = self.env().caller();
 
-        extern crate hex;
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            // add the user
+            self.authorized_users.insert(user.clone(), ());
+            if self.verbose_events {
+                self.env().emit_event(UserAdded { user });
+            }
+            Ok(())
+        }
+
+        /// Remove user from the oracle contract
+        #[ink(message)]
+        pub fn remove_user(&mut self, user: AccountId) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            // remove the user
+            self.authorized_users.take(&user);
+            if self.verbose_events {
+                self.env().emit_event(UserRemoved { user });
+            }
+            Ok(())
+        }
+
+        /// Stop the compromised oracle from delivering and pause new requests.
+        /// The first `REFUND_BATCH_SIZE` pending requests are refunded,
+        /// the rest with `refund_pending`.
+        #[ink(message)]
+        pub fn revoke_oracle(&mut self) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            let oracle = self.authorized_oracle;
+            self.authorized_oracle = AccountId::from([0x0; 32]);
+            self.paused = true;
+            self.env().emit_event(OracleRevoked { oracle });
+            self.refund_pending(REFUND_BATCH_SIZE)?;
+            Ok(())
+        }
+
+        /// Refund and remove up to `limit` pending requests.
+        /// Returns the number of refunded requests.
+        #[ink(message)]
+        pub fn refund_pending(&mut self, limit: u32) -> Result<u32, Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            // results waiting to be collected are kept
+            let request_ids: Vec<u64> = self
+                .requests
+                .keys()
+                .filter(|request_id| !self.results.contains_key(*request_id))
+                .take(limit as usize)
+                .cloned()
+                .collect();
+            for request_id in request_ids.iter() {
+                let (user_id, _, fee, _, _, _) = *self
+                    .requests
+                    .get(request_id)
+                    .ok_or(Error::RequestNotFound)?;
+                let callback_value = self.callback_value_(*request_id);
+                self.refund_(*request_id, user_id, fee + callback_value)?;
+                self.refund_subscribers_(*request_id)?;
+                self.remove_request_(*request_id);
+            }
+            Ok(request_ids.len() as u32)
+        }
+
+        /// Change the maximum number of stored requests, 0 means unlimited.
+        #[ink(message)]
+        pub fn set_max_requests(&mut self, max_requests: u32) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.max_requests = max_requests;
+            Ok(())
+        }
+
+        /// Register a PQL definition which can be requested
+        #[ink(message)]
+        pub fn allow_pql_hash(&mut self, pql_hash: Hash) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.allowed_hashes.insert(pql_hash, ());
+            Ok(())
+        }
+
+        /// Unregister a PQL definition
+        #[ink(message)]
+        pub fn disallow_pql_hash(&mut self, pql_hash: Hash) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.allowed_hashes.take(&pql_hash);
+            self.pql_labels.take(&pql_hash);
+            Ok(())
+        }
+
+        /// Register a PQL definition which can be requested under a short label
+        #[ink(message)]
+        pub fn register_pql(&mut self, pql_hash: Hash, label: [u8; 32]) -> Result<(), Error> {
+            self.allow_pql_hash(pql_hash)?;
+            self.pql_labels.insert(pql_hash, label);
+            Ok(())
+        }
+
+        /// Choose how the consumer's return value is decoded.
+        /// `true` (default) lets the consumers reject results but mis-decodes
+        /// consumers returning `()`, `false` works with any consumer but can't
+        /// tell a rejected result apart from an accepted one.
+        #[ink(message)]
+        pub fn set_callback_returns_result(&mut self, returns_result: bool) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.callback_returns_result = returns_result;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn callback_returns_result(&self) -> bool {
+            self.callback_returns_result
+        }
+
+        /// Release the rewards linearly over `vesting_blocks` since the last claim,
+        /// 0 releases them at once.
+        #[ink(message)]
+        pub fn set_vesting_blocks(&mut self, vesting_blocks: u32) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.vesting_blocks = vesting_blocks;
+            Ok(())
+        }
+
+        /// Change the number of failed deliveries before the result
+        /// is stored for the consumer to collect, 0 never falls back.
+        #[ink(message)]
+        pub fn set_max_delivery_attempts(&mut self, max_delivery_attempts: u32) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.max_delivery_attempts = max_delivery_attempts;
+            Ok(())
+        }
+
+        /// Variant indexes of the `OracleResult`s this deployment delivers,
+        /// consumers can check them against their own copy of the enum.
+        #[ink(message)]
+        pub fn supported_result_kinds(&self) -> Vec<u8> {
+            self.supported_kinds.clone()
+        }
+
+        /// Change the advertised `OracleResult` variant indexes
+        #[ink(message)]
+        pub fn set_supported_kinds(&mut self, kinds: Vec<u8>) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.supported_kinds = kinds;
+            Ok(())
+        }
+
+        /// Pause or resume accepting new requests
+        #[ink(message)]
+        pub fn set_paused(&mut self, paused: bool) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.paused = paused;
+            Ok(())
+        }
+
+        /// Emit or skip the non-essential events, see `verbose_events`
+        #[ink(message)]
+        pub fn set_verbose_events(&mut self, verbose_events: bool) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            self.verbose_events = verbose_events;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn verbose_events(&self) -> bool {
+            self.verbose_events
+        }
+
+        /// Add oracle to the round-robin pool new requests are assigned from
+        #[ink(message)]
+        pub fn add_oracle(&mut self, oracle: AccountId) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            if !self.oracle_pool.iter().any(|o| *o == oracle) {
+                self.oracle_pool.push(oracle);
+            }
+            self.env().emit_event(OracleAdded { oracle });
+            Ok(())
+        }
+
+        /// Remove oracle from the round-robin pool.
+        /// Requests already assigned to it have to be reassigned.
+        #[ink(message)]
+        pub fn remove_oracle(&mut self, oracle: AccountId) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            if let Some(idx) = self.oracle_pool.iter().position(|o| *o == oracle) {
+                self.oracle_pool.swap_remove(idx as u32);
+            }
+            self.env().emit_event(OracleRemoved { oracle });
+            Ok(())
+        }
+
+        /// Hand a pending request over to another oracle, e.g. on failover
+        #[ink(message)]
+        pub fn reassign_oracle(&mut self, request_id: u64, oracle: AccountId) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin {
+                return Err(Error::Unauthorized);
+            }
+
+            if !self.requests.contains_key(&request_id) {
+                return Err(Error::RequestNotFound);
+            }
+            if oracle != self.authorized_oracle && !self.oracle_pool.iter().any(|o| *o == oracle) {
+                return Err(Error::ValueError);
+            }
+            self.assign_oracle_(request_id, oracle);
+            Ok(())
+        }
+
+        /// Oracle the request is assigned to, if any
+        #[ink(message)]
+        pub fn assigned_oracle(&self, request_id: u64) -> Option<AccountId> {
+            self.assigned_oracles.get(&request_id).cloned()
+        }
+
+        /// Extend the expiry of a pending request, e.g. during oracle downtime.
+        /// The total validity is capped at twice the `max_valid_period`.
+        #[ink(message)]
+        pub fn extend_request(&mut self, request_id: u64, extra_blocks: u32) -> Result<(), Error> {
+            let from = self.env().caller();
+
+            if from != self.admin && from != self.authorized_oracle {
+                return Err(Error::Unauthorized);
+            }
+
+            let current_block = self.env().block_number();
+            let max_period = 2 * self.max_valid_period as u64;
+            let request = self.requests.get_mut(&request_id).ok_or(Error::RequestNotFound)?;
+            if request.1 < current_block {
+                return Err(Error::RequestExpired);
+            }
+
+            let max_valid_till =
+                mathutil::checked_add_block(request.5, max_period).ok_or(Error::ValueError)?;
+            let valid_till = core::cmp::min(
+                mathutil::checked_add_block(request.1, extra_blocks as u64)
+                    .ok_or(Error::ValueError)?,
+                max_valid_till,
+            );
+            request.1 = valid_till;
+            self.env().emit_event(RequestExtended {
+                request_id,
+                valid_till,
+            });
+            Ok(())
+        }
+
+        /// Remove expired request to free contract storage.
+        /// The caller earns up to `cleanup_reward` of the refunded fee,
+        /// the user additionally gets the oracle's `penalty_per_expiry`.
+        /// Delivery records older than `max_valid_period` blocks are pruned as well,
+        /// and results nobody collected within as many blocks after the expiry released.
+        #[ink(message)]
+        pub fn clear_expired(&mut self, request_id: u64) -> Result<(), Error> {
+            if let Some(delivered_at) = self.delivered.get(&request_id) {
+                let prune_after = delivered_at.saturating_add(self.max_valid_period as u64);
+                if prune_after < self.env().block_number() {
+                    self.delivered.take(&request_id);
+                    return Ok(());
+                } else {
+                    return Err(Error::RequestNotExpired);
+                }
+            }
+
+            // the result is waiting to be collected
+            if self.results.contains_key(&request_id) {
+                return self.release_result_(request_id);
+            }
+
+            if let Some(request) = self.requests.get(&request_id) {
+                let (user_id, valid_till, fee, _, _, _) = *request;
+                if valid_till < self.env().block_number() {
+                    let callback_value = self.callback_value_(request_id);
+                    let cleanup_reward = if self.cleanup_reward < fee {
+                        self.cleanup_reward
+                    } else {
+                        fee
+                    };
+                    let penalty = self.penalize_(request_id);
+                    let refunded = fee + callback_value - cleanup_reward + penalty;
+                    self.pay_(self.env().caller(), cleanup_reward)?;
+                    self.pay_(user_id, refunded)?;
+                    self.env().emit_event(RequestInvalidated {
+                        request_id,
+                        refunded,
+                        cleanup_reward,
+                        penalty,
+                    });
+                    self.refund_subscribers_(request_id)?;
+                    self.remove_request_(request_id);
+                    self.expired_total += 1;
+                    return Ok(());
+                } else {
+                    return Err(Error::RequestNotExpired);
+                }
+            } else {
+                return Err(Error::RequestNotFound);
+            }
+        }
+
+        //
+        // Other
+        //
+
+        /// Version of the oracle request/callback interface
+        /// spoken by this contract.
+        #[ink(message)]
+        pub fn abi_version(&self) -> u32 {
+            ABI_VERSION
+        }
+
+        /// Requests the user can still make in the current quota window
+        #[ink(message)]
+        pub fn remaining_quota(&self, who: AccountId) -> u32 {
+            if self.quota_count == 0 {
+                return u32::MAX;
+            }
+            self.quota_count.saturating_sub(self.quota_used_(who))
+        }
+
+        /// Label the PQL definition was registered with
+        #[ink(message)]
+        pub fn label_of(&self, pql_hash: Hash) -> Option<[u8; 32]> {
+            self.pql_labels.get(&pql_hash).copied()
+        }
+
+        /// Request outcomes as (delivered, expired, cancelled)
+        #[ink(message)]
+        pub fn stats(&self) -> (u64, u64, u64) {
+            (self.delivered_total, self.expired_total, self.cancelled_total)
+        }
+
+        /// Liveness probe returning
+        /// (current block, outstanding request count, contract balance).
+        #[ink(message)]
+        pub fn health(&self) -> (u64, u64, Balance) {
+            (
+                self.env().block_number(),
+                self.outstanding_requests,
+                self.env().balance(),
+            )
+        }
+
+        /// Fee currently charged per request, on top of the `callback_value`.
+        #[ink(message, selector = "0xFEE00000")]
+        pub fn get_fee(&self) -> Balance {
+            self.fee
+        }
+
+        /// Fee in effect at the given block.
+        /// Blocks before the oldest kept change return the oldest known fee.
+        #[ink(message)]
+        pub fn get_fee_at(&self, block: u64) -> Balance {
+            let mut fee_at = None;
+            for (changed_at, fee) in self.fee_history.iter() {
+                if *changed_at > block {
+                    break;
+                }
+                fee_at = Some(*fee);
+            }
+            match (fee_at, self.fee_history.first()) {
+                (Some(fee), _) => fee,
+                // the fee was never changed before the block
+                (None, _) if self.fee_history.len() < FEE_HISTORY_LEN => (0 as u128).into(),
+                (None, Some((_, oldest))) => *oldest,
+                (None, None) => (0 as u128).into(),
+            }
+        }
+
+        /// Append to the fee history, dropping the oldest entry when full
+        fn record_fee_(&mut self, fee: Balance) {
+            if self.fee_history.len() >= FEE_HISTORY_LEN {
+                // move the oldest entry to the end and drop it
+                for i in 1..self.fee_history.len() {
+                    self.fee_history.swap(i - 1, i);
+                }
+                self.fee_history.pop();
+            }
+            self.fee_history.push((self.env().block_number(), fee));
+        }
+
+        // TODO: check if this is private & internal only
+        fn claim_(&mut self) -> Result<(), Error> {
+            // the rewards of a revoked oracle go to its successor
+            if self.revoked_() {
+                return Ok(());
+            }
+
+            // every oracle is paid what vested on its own schedule,
+            // the unvested rest stays with it for the next claim
+            self.split_rewards_();
+            let oracles: Vec<AccountId> = self.oracle_rewards.keys().copied().collect();
+            for oracle in oracles {
+                let amount = self.release_(oracle);
+                self.pay_(oracle, amount)?;
+                self.env().emit_event(RewardsClaimed {
+                    oracle,
+                    to: oracle,
+                    amount,
+                });
+            }
+            Ok(())
+        }
+
+        /// Split the accrued rewards among the oracles by their deliveries
+        /// since the last split, rounding leftovers are kept for the next one.
+        /// Without deliveries they belong to the authorized oracle.
+        fn split_rewards_(&mut self) {
+            let deliveries: Vec<(AccountId, u64)> =
+                self.delivered_by.iter().map(|(o, n)| (*o, *n)).collect();
+            let total: u64 = deliveries.iter().map(|(_, n)| n).sum();
+            if total == 0 {
+                if !self.revoked_() {
+                    let rewards = self.accrued_rewards;
+                    self.accrued_rewards = (0 as u128).into();
+                    self.credit_rewards_(self.authorized_oracle, rewards);
+                }
+                return;
+            }
+
+            let accrued = self.accrued_rewards;
+            for (oracle, count) in deliveries {
+                self.delivered_by.take(&oracle);
+                let share = accrued * count as u128 / total as u128;
+                self.accrued_rewards -= share;
+                self.credit_rewards_(oracle, share);
+            }
+        }
+
+        fn credit_rewards_(&mut self, oracle: AccountId, amount: Balance) {
+            if amount > (0 as u128).into() {
+                let rewards = self.oracle_rewards.get(&oracle).copied().unwrap_or(0);
+                self.oracle_rewards.insert(oracle, rewards + amount);
+            }
+        }
+
+        /// Rewards split off to the oracle plus its share of the accrued rewards
+        fn rewards_of_(&self, oracle: AccountId) -> Balance {
+            let split = self.oracle_rewards.get(&oracle).copied().unwrap_or(0);
+            let total: u64 = self.delivered_by.iter().map(|(_, n)| *n).sum();
+            let share = if total > 0 {
+                self.accrued_rewards * self.deliveries_by(oracle) as u128 / total as u128
+            } else if oracle == self.authorized_oracle && !self.revoked_() {
+                self.accrued_rewards
+            } else {
+                (0 as u128).into()
+            };
+            split + share
+        }
+
+        /// Part of `rewards` released since the oracle was last paid out
+        fn released_(&self, oracle: AccountId, rewards: Balance) -> Balance {
+            let last_claim = self.last_claim_blocks.get(&oracle).copied().unwrap_or(0);
+            let elapsed = self.env().block_number().saturating_sub(last_claim);
+            mathutil::linear_release(rewards, elapsed, self.vesting_blocks)
+        }
+
+        /// Take the released rewards off the oracle's split and restart its vesting
+        fn release_(&mut self, oracle: AccountId) -> Balance {
+            let rewards = self.oracle_rewards.get(&oracle).copied().unwrap_or(0);
+            let released = self.released_(oracle, rewards);
+            if released == rewards {
+                self.oracle_rewards.take(&oracle);
+            } else {
+                self.oracle_rewards.insert(oracle, rewards - released);
+            }
+            self.last_claim_blocks.insert(oracle, self.env().block_number());
+            released
+        }
+
+        /// Count the delivery towards the oracle's share of the rewards
+        fn record_delivery_(&mut self, oracle: AccountId) {
+            self.delivered_total += 1;
+            let count = self.deliveries_by(oracle);
+            self.delivered_by.insert(oracle, count + 1);
+        }
+
+        /// Whether the oracle was revoked and not replaced yet
+        fn revoked_(&self) -> bool {
+            self.authorized_oracle == AccountId::from([0x0; 32])
+        }
+
+        /// Pay the oracle's share of the rewards to `to`, the shares
+        /// of the other oracles are kept for them.
+        fn claim_to_(&mut self, to: AccountId) -> Result<(), Error> {
+            let oracle = self.authorized_oracle;
+            self.split_rewards_();
+            let rewards = self.oracle_rewards.get(&oracle).copied().unwrap_or(0);
+            let balance = self.released_(oracle, rewards);
+            if balance > (0 as u128).into() {
+                let tx = self.env().transfer(to, balance);
+                return match tx {
+                    Ok(_) => {
+                        self.release_(oracle);
+                        let event = RewardsClaimed {
+                            oracle: self.authorized_oracle,
+                            to,
+                            amount: balance,
+                        };
+                        self.env().emit_event(event);
+                        Ok(())
+                    }
+                    Err(err) => match err {
+                        ink_env::Error::BelowSubsistenceThreshold => {
+                            Err(Error::BelowSubsistenceThreshold)
+                        }
+                        _ => Err(Error::TransferFailed),
+                    },
+                };
+            }
+            Ok(())
+        }
+
+        /// Deliver the result to the consumer's `selector`,
+        /// followed by the revealed and the next secret if given.
+        fn deliver_(
+            &mut self,
+            request_id: u64,
+            to: AccountId,
+            selector: [u8; 4],
+            value: Balance,
+            result: &OracleResult,
+            secret: Option<([u8; 32], Hash)>,
+        ) -> Result<(), Error> {
+            // note: this will not work off-chain, see:
+            // https://paritytech.github.io/ink/src/ink_env/call/call_builder.rs.html#53
+
+            // // method 1:
+            // // https://paritytech.github.io/ink/ink_env/fn.invoke_contract.html
+            //
+            // use ink_env::call::{
+            //     utils::{ReturnType},
+            //     Selector, ExecutionInput, CallParams};
+            // let selector = Selector::new([
+            //     0xB1, 0x6B, 0x00, 0xB5,
+            // ]);
+            // let calldata: CallParams<ink_env::DefaultEnvironment, _, ()> = CallParams{
+            //     /// smart contract we are calling
+            //     callee: callback_addr,
+            //     /// Default gas limit
+            //     gas_limit: 1_000_000 as u64,
+            //     /// Not sending any funds
+            //     transferred_value: (0 as u128).into(),
+            //     /// Not expecting a return type
+            //     return_type: ReturnType::default(),
+            //     /// Function and its args??
+            //     exec_input: ExecutionInput::new(selector).push_arg(42)
+            // };
+            // if let Err(err) = ink_env::invoke_contract(&calldata) {
+            //     return Err(Error::CallbackExecutionFailed);
+            // }
+
+            // method 2:
+            // https://paritytech.github.io/ink/ink_env/call/fn.build_call.html
+            //
+            //
+            // A consumer returning `Result<(), Error>` has its error variant
+            // scale encoded as a single byte, so we decode it as `u8`.
+            use ink_env::call::{ExecutionInput, Selector};
+            let input = ExecutionInput::new(Selector::new(selector))
+                .push_arg(request_id)
+                .push_arg(result);
+            let callback = match secret {
+                Some((secret, next_secret_hash)) => self.fire_callback_(
+                    to,
+                    value,
+                    input.push_arg(secret).push_arg(next_secret_hash),
+                ),
+                None => self.fire_callback_(to, value, input),
+            };
+            match callback {
+                Ok(Ok(())) => (),
+                Ok(Err(code)) => {
+                    // the consumer rejected the result
+                    self.env().emit_event(CallbackFailed {
+                        request_id,
+                        to,
+                        code,
+                    });
+                    return Err(Error::CallbackRejected);
+                }
+                Err(_) => {
+                    self.env().emit_event(CallbackFailed {
+                        request_id,
+                        to,
+                        code: CALL_FAILED_CODE,
+                    });
+                    return Err(Error::CallbackExecutionFailed);
+                }
+            }
+            Ok(())
+        }
+
+        /// Call the consumer, decoding its return value as configured
+        /// by `set_callback_returns_result`.
+        fn fire_callback_<Args: scale::Encode>(
+            &self,
+            to: AccountId,
+            value: Balance,
+            input: ink_env::call::ExecutionInput<Args>,
+        ) -> Result<Result<(), u8>, ink_env::Error> {
+            use ink_env::call::{build_call, utils::ReturnType};
+            if self.callback_returns_result {
+                build_call::<ink_env::DefaultEnvironment>()
+                    .callee(to)
+                    .gas_limit(1_000_000)
+                    .transferred_value(value)
+                    .exec_input(input)
+                    .returns::<ReturnType<Result<(), u8>>>()
+                    .fire()
+            } else {
+                build_call::<ink_env::DefaultEnvironment>()
+                    .callee(to)
+                    .gas_limit(1_000_000)
+                    .transferred_value(value)
+                    .exec_input(input)
+                    .returns::<()>()
+                    .fire()
+                    .map(Ok)
+            }
+        }
+
+        /// Checks shared by the deliveries, returns the requester and the fee.
+        /// An expired request is refunded and removed.
+        fn check_delivery_(
+            &mut self,
+            from: AccountId,
+            request_id: u64,
+            pql_hash: Hash,
+        ) -> Result<(AccountId, Balance), Error> {
+            if !self.is_oracle_(from, request_id) {
+                return Err(Error::Unauthorized);
+            }
+
+            // the result is waiting to be collected
+            if self.results.contains_key(&request_id) {
+                return Err(Error::ResultPending);
+            }
+
+            // check if request_id has expired
+            let (user_id, valid_till, fee, _, request_hash, _) =
+                *self.requests.get(&request_id).ok_or(Error::RequestNotFound)?;
+            if valid_till < self.env().block_number() {
+                self.env().emit_event(DeliveryTooLate {
+                    request_id,
+                    oracle: from,
+                    blocks_late: self.env().block_number() - valid_till,
+                });
+                let callback_value = self.callback_value_(request_id);
+                self.refund_(request_id, user_id, fee + callback_value)?;
+                self.refund_subscribers_(request_id)?;
+                self.remove_request_(request_id);
+                self.expired_total += 1;
+                return Err(Error::RequestExpired);
+            }
+            // the result has to be for the requested job
+            if request_hash != pql_hash {
+                return Err(Error::HashMismatch);
+            }
+            Ok((user_id, fee))
+        }
+
+        /// Checks shared by `request` and `validate_request`
+        fn check_request_(
+            &self,
+            from: AccountId,
+            valid_period: u32,
+            fee: Balance,
+            callback_value: Balance,
+            payment: Balance,
+        ) -> Result<(), Error> {
+            if !self.authorized_users.contains_key(&from) {
+                return Err(Error::Unauthorized);
+            }
+
+            if self.paused {
+                return Err(Error::Paused);
+            }
+
+            if let Some(last) = self.last_request.get(&from) {
+                if self.env().block_number().saturating_sub(*last) < self.cooldown_blocks as u64 {
+                    return Err(Error::Cooldown);
+                }
+            }
+
+            if self.remaining_quota(from) == 0 {
+                return Err(Error::QuotaExceeded);
+            }
+
+            // require some reasonable valid_period
+            if valid_period < self.min_valid_period || valid_period > self.max_valid_period {
+                return Err(Error::ValueError);
+            }
+
+            let required = fee + callback_value;
+            if required > (0 as u128).into() && payment != required {
+                return Err(Error::PaymentRequired);
+            }
+            Ok(())
+        }
+
+        /// Requests made by the user in the current quota window
+        fn quota_used_(&self, who: AccountId) -> u32 {
+            match self.quota_usage.get(&who) {
+                Some((window_start, count))
+                    if self.env().block_number()
+                        < window_start.saturating_add(self.quota_window as u64) =>
+                {
+                    *count
+                }
+                _ => 0,
+            }
+        }
+
+        /// The result has to be of the kind the request expects
+        fn check_result_kind_(&self, request_id: u64, result: &OracleResult) -> Result<(), Error> {
+            let matches = match (self.result_kinds.get(&request_id), result) {
+                (None, _) => true,
+                (Some(&RESULT_KIND_NUMERIC), OracleResult::Numeric(_)) => true,
+                (Some(&RESULT_KIND_RAW_BYTES), OracleResult::RawBytes(_)) => true,
+                _ => false,
+            };
+            if !matches {
+                return Err(Error::ResultTypeMismatch);
+            }
+            Ok(())
+        }
+
+        /// The zero hash never resolves to a PQL definition
+        fn check_pql_hash_(&self, pql_hash: &Hash) -> Result<(), Error> {
+            if *pql_hash == Hash::from([0x0; 32]) {
+                return Err(Error::InvalidPqlHash);
+            }
+            if self.restrict_pql_hashes && !self.allowed_hashes.contains_key(pql_hash) {
+                return Err(Error::InvalidPqlHash);
+            }
+            Ok(())
+        }
+
+        /// Unexpired request already created for the pql hash, if deduplicating
+        fn active_request_(&self, pql_hash: &Hash) -> Option<u64> {
+            if !self.dedup_requests {
+                return None;
+            }
+            let request_id = self.active_hashes.get(pql_hash)?;
+            match self.requests.get(request_id) {
+                Some((_, valid_till, _, _, _, _)) if *valid_till >= self.env().block_number() => {
+                    Some(*request_id)
+                }
+                _ => None,
+            }
+        }
+
+        /// Whether the account may deliver the request, only the assigned
+        /// oracle can, any registered oracle if unassigned
+        fn is_oracle_(&self, from: AccountId, request_id: u64) -> bool {
+            if self.revoked_() {
+                return false;
+            }
+            match self.assigned_oracles.get(&request_id) {
+                Some(oracle) => from == *oracle,
+                None => from == self.authorized_oracle || self.oracle_pool.iter().any(|o| *o == from),
+            }
+        }
+
+        /// Next oracle from the round-robin pool
+        fn next_oracle_(&mut self) -> Option<AccountId> {
+            if self.oracle_pool.is_empty() {
+                return None;
+            }
+            let oracle = *self.oracle_pool.get(self.next_oracle % self.oracle_pool.len())?;
+            self.next_oracle = self.next_oracle.wrapping_add(1);
+            Some(oracle)
+        }
+
+        fn assign_oracle_(&mut self, request_id: u64, oracle: AccountId) {
+            self.assigned_oracles.insert(request_id, oracle);
+            self.env().emit_event(RequestAssigned { request_id, oracle });
+        }
+
+        /// Remove the request from storage along with its dedup entry
+        fn remove_request_(
+            &mut self,
+            request_id: u64,
+        ) -> Option<(AccountId, u64, Balance, u8, Hash, u64)> {
+            let request = self.requests.take(&request_id)?;
+            self.outstanding_requests = self.outstanding_requests.saturating_sub(1);
+            self.callback_values.take(&request_id);
+            self.assigned_oracles.take(&request_id);
+            self.result_kinds.take(&request_id);
+            self.callback_addrs.take(&request_id);
+            self.annotations.take(&request_id);
+            self.delivery_attempts.take(&request_id);
+            self.results.take(&request_id);
+            let (_, _, _, _, pql_hash, _) = request;
+            if self.active_hashes.get(&pql_hash) == Some(&request_id) {
+                self.active_hashes.take(&pql_hash);
+            }
+            Some(request)
+        }
+
+        /// Refund the subscribers of an undelivered request
+        fn refund_subscribers_(&mut self, request_id: u64) -> Result<(), Error> {
+            for (user_id, _, fee, callback_value) in
+                self.subscribers.take(&request_id).unwrap_or_default()
+            {
+                self.refund_(request_id, user_id, fee + callback_value)?;
+            }
+            Ok(())
+        }
+
+        /// Count the failed delivery, and store the result for the consumer
+        /// to collect after `max_delivery_attempts`. The delivery counts
+        /// towards the oracle's rewards once the result is collected or
+        /// released. Returns whether the result was stored.
+        fn fall_back_to_poll_(
+            &mut self,
+            request_id: u64,
+            oracle: AccountId,
+            result: &OracleResult,
+        ) -> bool {
+            let attempts = self.delivery_attempts.get(&request_id).copied().unwrap_or(0) + 1;
+            if self.max_delivery_attempts == 0 || attempts < self.max_delivery_attempts {
+                self.delivery_attempts.insert(request_id, attempts);
+                return false;
+            }
+            self.delivery_attempts.take(&request_id);
+            self.results.insert(request_id, (oracle, result.clone()));
+            self.env().emit_event(FallbackToPoll { request_id });
+            true
+        }
+
+        /// Release a result the consumer didn't collect within `max_valid_period`
+        /// blocks after the request expired. The oracle delivered, so it earns
+        /// the fee, and the `callback_value` goes back to the requester.
+        fn release_result_(&mut self, request_id: u64) -> Result<(), Error> {
+            let (user_id, valid_till, _, _, _, _) =
+                *self.requests.get(&request_id).ok_or(Error::RequestNotFound)?;
+            let release_after = valid_till.saturating_add(self.max_valid_period as u64);
+            if release_after >= self.env().block_number() {
+                return Err(Error::RequestNotExpired);
+            }
+
+            let (oracle, _) = self.results.take(&request_id).ok_or(Error::RequestNotFound)?;
+            let callback_value = self.callback_value_(request_id);
+            self.record_delivery_(oracle);
+            if let Some((_, _, fee, _, _, _)) = self.remove_request_(request_id) {
+                self.split_fee_(fee)?;
+            }
+            self.pay_(user_id, callback_value)?;
+            self.refund_subscribers_(request_id)?;
+            self.env().emit_event(RequestInvalidated {
+                request_id,
+                refunded: callback_value,
+                cleanup_reward: (0 as u128).into(),
+                penalty: (0 as u128).into(),
+            });
+            Ok(())
+        }
+
+        /// The consumer rejected the result, the requester gets the fee and
+        /// the `callback_value` back. The request stays open, so the oracle
+        /// can retry the delivery unpaid.
+        fn reject_delivery_(&mut self, request_id: u64) -> Result<(), Error> {
+            let callback_value = self.callback_values.take(&request_id).unwrap_or(0);
+            let (user_id, fee) = match self.requests.get_mut(&request_id) {
+                Some(request) => {
+                    let fee = request.2;
+                    request.2 = (0 as u128).into();
+                    (request.0, fee)
+                }
+                None => return Ok(()),
+            };
+            self.refund_(request_id, user_id, fee + callback_value)
+        }
+
+        /// Address the result of the request is delivered to
+        fn callback_addr_(&self, request_id: u64, user_id: AccountId) -> AccountId {
+            *self.callback_addrs.get(&request_id).unwrap_or(&user_id)
+        }
+
+        /// Value to forward to the consumer of the request
+        fn callback_value_(&self, request_id: u64) -> Balance {
+            *self.callback_values.get(&request_id).unwrap_or(&0)
+        }
+
+        /// Record a refund the user can withdraw later on
+        fn owe_refund_(&mut self, user_id: AccountId, amount: Balance) {
+            if amount > (0 as u128).into() {
+                let owed = self.pending_refund(user_id);
+                self.pending_refunds.insert(user_id, owed + amount);
+            }
+        }
+
+        /// Take up to `penalty_per_expiry` from the stake of the oracle
+        /// which let the request expire, the rest from its own rewards.
+        /// Nobody is penalized for unassigned requests of a revoked oracle.
+        fn penalize_(&mut self, request_id: u64) -> Balance {
+            let oracle = match self.assigned_oracles.get(&request_id) {
+                Some(oracle) => *oracle,
+                None if !self.revoked_() => self.authorized_oracle,
+                None => return (0 as u128).into(),
+            };
+            let stake = self.stake_of(oracle);
+            let slashed = if self.penalty_per_expiry < stake {
+                self.penalty_per_expiry
+            } else {
+                stake
+            };
+            if slashed > (0 as u128).into() {
+                self.oracle_stakes.insert(oracle, stake - slashed);
+                self.env().emit_event(Slashed {
+                    oracle,
+                    amount: slashed,
+                });
+            }
+
+            // only the oracle's own rewards are cut, never the other oracles' shares
+            let remaining = self.penalty_per_expiry - slashed;
+            self.split_rewards_();
+            let own_rewards = self.oracle_rewards.get(&oracle).copied().unwrap_or(0);
+            let taken = if remaining < own_rewards {
+                remaining
+            } else {
+                own_rewards
+            };
+            if taken > (0 as u128).into() {
+                self.oracle_rewards.insert(oracle, own_rewards - taken);
+            }
+            slashed + taken
+        }
+
+        /// Split the fee of a delivered request between the oracle and the treasury
+        fn split_fee_(&mut self, fee: Balance) -> Result<(), Error> {
+            let oracle_share =
+                mathutil::saturating_fee_mul(fee, self.gas_refund_bps).ok_or(Error::ValueError)?;
+            self.accrued_rewards += oracle_share;
+            self.treasury_balance += fee - oracle_share;
+            Ok(())
+        }
+
+        // TODO: check if this is private & internal only
+        fn refund_(
+            &mut self,
+            request_id: u64,
+            user_id: AccountId,
+            fee: Balance,
+        ) -> Result<(), Error> {
+            self.pay_(user_id, fee)?;
+            let event = RequestInvalidated {
+                request_id,
+                refunded: fee,
+                cleanup_reward: (0 as u128).into(),
+                penalty: (0 as u128).into(),
+            };
+            self.env().emit_event(event);
+            Ok(())
+        }
+
+        /// Transfer the amount, or owe it if the transfer fails,
+        /// e.g. the receiver is a contract rejecting transfers or the
+        /// contract would drop below the subsistence threshold.
+        /// The user can always withdraw the owed amount later on.
+        fn pay_(&mut self, to: AccountId, amount: Balance) -> Result<(), Error> {
+            if amount > (0 as u128).into() {
+                if self.env().balance() < amount || self.env().transfer(to, amount).is_err() {
+                    self.owe_refund_(to, amount);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ink_lang as ink;
+        use scale::{Decode, Encode};
+
+        extern crate hex;
         use hex::FromHex;
 
         #[ink::test]
-        fn test_defaults() {
-            // alice is admin
+        fn test_defaults() {
+            // alice is admin
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+
+            // default contract
+            let contract = TrustedOracle::default();
+            assert!(contract.admin == accounts.alice);
+            assert!(contract.authorized_oracle == accounts.alice);
+            assert!(contract.authorized_users.contains_key(&accounts.alice));
+        }
+
+        #[ink::test]
+        fn test_make_free_request() {
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            contract.request(pql_hash, 10, 0, 0);
+        }
+
+        #[ink::test]
+        fn test_make_paid_request() {
+            // alice is admin
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+
+            // admin sets the fee
+            let mut contract = TrustedOracle::default();
+            let fee: Balance = (100 as u128).into();
+            assert!(contract.set_fee(fee).is_ok());
+            assert_eq!(contract.get_fee(), fee);
+
+            let pql_hash = sample_ipfs_hash();
+
+            // payment required
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::PaymentRequired));
+
+            // kinda hacky way of sending value into contract
+            // assert!(contract.request(pql_hash, 10, {value: 10}).is_ok());
+            set_sender(accounts.alice);
+            set_balance(accounts.alice, fee);
+            let mut data =
+                ink_env::test::CallData::new(ink_env::call::Selector::new(REQUEST_SELECTOR));
+            data.push_arg(&pql_hash);
+            data.push_arg(&10);
+            data.push_arg(&0u8);
+            data.push_arg(&(0 as Balance));
+
+            // Send "fee" value into the contract
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.alice,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                fee,
+                data,
+            );
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+        }
+
+        #[ink::test]
+        fn test_request_with_tip() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.set_fee(100).is_ok());
+            let pql_hash = sample_ipfs_hash();
+
+            // the tip has to be paid on top of the fee
+            assert_eq!(contract.request_with_tip(pql_hash, 10, 0, 0, 5), Err(Error::PaymentRequired));
+            for _ in 0..2 {
+                ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                    accounts.alice,
+                    contract_id(),
+                    DEFAULT_GAS_LIMIT,
+                    105,
+                    ink_env::test::CallData::new(ink_env::call::Selector::new(
+                        REQUEST_WITH_TIP_SELECTOR,
+                    )),
+                );
+                assert!(contract.request_with_tip(pql_hash, 10, 0, 0, 5).is_ok());
+            }
+
+            // the oracle earns the tip along with the fee
+            assert!(contract
+                .simple_callback(1, accounts.alice, OracleResult::Numeric(1))
+                .is_ok());
+            assert_eq!(contract.pending_rewards(), 105);
+
+            // and it is refunded along with the fee
+            set_balance(contract_id(), 0);
+            assert!(contract.cancel_request(2).is_ok());
+            assert_eq!(contract.pending_refund(accounts.alice), 105);
+        }
+
+        #[ink::test]
+        fn test_request_on_behalf() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let fee: Balance = (100 as u128).into();
+            assert!(contract.set_fee(fee).is_ok());
+            let pql_hash = sample_ipfs_hash();
+
+            // alice deposits for two requests
+            set_balance(accounts.alice, fee * 2);
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.alice,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                fee * 2,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+            contract.deposit();
+            assert_eq!(contract.deposit_of(accounts.alice), fee * 2);
+
+            // bob is not approved yet
+            set_sender(accounts.bob);
+            assert_eq!(
+                contract.request_on_behalf(accounts.alice, pql_hash, 10),
+                Err(Error::InsufficientFunds)
+            );
+
+            set_sender(accounts.alice);
+            contract.approve(accounts.bob, fee);
+            set_sender(accounts.bob);
+            assert_eq!(contract.request_on_behalf(accounts.alice, pql_hash, 10), Ok(1));
+            assert_eq!(contract.requests.get(&1).unwrap().0, accounts.alice);
+            assert_eq!(contract.allowance(accounts.alice, accounts.bob), 0);
+            assert_eq!(contract.deposit_of(accounts.alice), fee);
+
+            // allowance is used up
+            assert_eq!(
+                contract.request_on_behalf(accounts.alice, pql_hash, 10),
+                Err(Error::InsufficientFunds)
+            );
+        }
+
+        #[ink::test]
+        fn test_fee_history() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.set_fee(10).is_ok());
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            assert!(contract.set_fee(20).is_ok());
+
+            assert_eq!(contract.get_fee_at(0), 10);
+            assert_eq!(contract.get_fee_at(1), 10);
+            assert_eq!(contract.get_fee_at(2), 20);
+            assert_eq!(contract.get_fee_at(100), 20);
+
+            // only the latest changes are kept
+            for fee in 0..FEE_HISTORY_LEN {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+                assert!(contract.set_fee(100 + fee as Balance).is_ok());
+            }
+            assert_eq!(contract.fee_history.len(), FEE_HISTORY_LEN);
+            assert_eq!(contract.get_fee_at(0), 100);
+        }
+
+        #[ink::test]
+        fn test_free_request_refunds_payment() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            let payment: Balance = (100 as u128).into();
+
+            set_balance(accounts.alice, payment);
+            set_balance(contract_id(), payment);
+            let mut data =
+                ink_env::test::CallData::new(ink_env::call::Selector::new(REQUEST_SELECTOR));
+            data.push_arg(&pql_hash);
+            data.push_arg(&10);
+            data.push_arg(&0u8);
+            data.push_arg(&(0 as Balance));
+
+            // attach value to a free request
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.alice,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                payment,
+                data,
+            );
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            // the payment is either sent back or owed to alice
+            assert_eq!(
+                get_balance(accounts.alice) + contract.pending_refund(accounts.alice),
+                payment * 2
+            );
+        }
+
+        #[ink::test]
+        fn test_health() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert_eq!(contract.health().1, 0);
+
+            assert!(contract.request(sample_ipfs_hash(), 10, 0, 0).is_ok());
+            assert_eq!(contract.health().1, 1);
+
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert!(contract.clear_expired(1).is_ok());
+            let (block, outstanding, _) = contract.health();
+            assert_eq!(block, 11);
+            assert_eq!(outstanding, 0);
+        }
+
+        #[ink::test]
+        fn test_oracle_assignment() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+
+            // unassigned without a pool
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert_eq!(contract.assigned_oracle(1), None);
+
+            assert!(contract.add_oracle(accounts.bob).is_ok());
+            assert!(contract.add_oracle(accounts.charlie).is_ok());
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert_eq!(contract.assigned_oracle(2), Some(accounts.bob));
+            assert_eq!(contract.assigned_oracle(3), Some(accounts.charlie));
+            assert_eq!(contract.assigned_oracle(4), Some(accounts.bob));
+
+            // only the assigned oracle may deliver
+            assert_eq!(
+                contract.simple_callback(2, accounts.django, OracleResult::Numeric(1)),
+                Err(Error::Unauthorized)
+            );
+            set_sender(accounts.charlie);
+            assert_eq!(
+                contract.simple_callback(2, accounts.django, OracleResult::Numeric(1)),
+                Err(Error::Unauthorized)
+            );
+
+            // failover
+            set_sender(accounts.alice);
+            assert_eq!(contract.reassign_oracle(2, accounts.django), Err(Error::ValueError));
+            assert!(contract.reassign_oracle(2, accounts.charlie).is_ok());
+            assert_eq!(contract.assigned_oracle(2), Some(accounts.charlie));
+        }
+
+        #[ink::test]
+        fn test_stats() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            for _ in 0..3 {
+                assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            }
+
+            // only the requester can cancel
+            set_sender(accounts.bob);
+            assert_eq!(contract.cancel_request(1), Err(Error::Unauthorized));
+            set_sender(accounts.alice);
+            assert!(contract.cancel_request(1).is_ok());
+            assert_eq!(contract.cancel_request(1), Err(Error::RequestNotFound));
+
+            assert!(contract
+                .simple_callback(2, accounts.bob, OracleResult::Numeric(1))
+                .is_ok());
+
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert!(contract.clear_expired(3).is_ok());
+            assert_eq!(contract.stats(), (1, 1, 1));
+        }
+
+        #[ink::test]
+        fn test_rewards_split_by_deliveries() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.add_oracle(accounts.bob).is_ok());
+            assert!(contract.add_oracle(accounts.charlie).is_ok());
+            assert!(contract.set_fee(25).is_ok());
+            let deliveries = [
+                (1, accounts.bob),
+                (2, accounts.charlie),
+                (3, accounts.charlie),
+                (4, accounts.charlie),
+            ];
+            for (request_id, oracle) in &deliveries {
+                assert_eq!(paid_request(&mut contract, accounts.alice, 25), *request_id);
+                set_sender(accounts.alice);
+                assert!(contract.reassign_oracle(*request_id, *oracle).is_ok());
+                set_sender(*oracle);
+                assert!(contract
+                    .simple_callback(*request_id, accounts.eve, OracleResult::Numeric(1))
+                    .is_ok());
+            }
+            assert_eq!(contract.deliveries_by(accounts.bob), 1);
+            assert_eq!(contract.deliveries_by(accounts.charlie), 3);
+
+            assert_eq!(contract.pending_rewards(), 100);
+
+            // the contract can't transfer, so the rewards are owed instead
+            set_balance(contract_id(), 0);
+            set_sender(accounts.alice);
+            assert!(contract.claim_rewards().is_ok());
+            assert_eq!(contract.pending_refund(accounts.bob), 25);
+            assert_eq!(contract.pending_refund(accounts.charlie), 75);
+            assert_eq!(contract.pending_rewards(), 0);
+            assert_eq!(contract.deliveries_by(accounts.charlie), 0);
+        }
+
+        #[ink::test]
+        fn test_quiet_mode() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.verbose_events());
+            assert!(contract.set_verbose_events(false).is_ok());
+            assert!(!contract.verbose_events());
+
+            let events = ink_env::test::recorded_events().count();
+            assert!(contract.add_user(accounts.bob).is_ok());
+            assert!(contract.remove_user(accounts.bob).is_ok());
+            assert!(contract.set_fee(1).is_ok());
+            assert_eq!(ink_env::test::recorded_events().count(), events);
+
+            // the request lifecycle is still logged
+            assert!(contract.set_fee(0).is_ok());
+            assert!(contract.request(sample_ipfs_hash(), 10, 0, 0).is_ok());
+            assert_eq!(ink_env::test::recorded_events().count(), events + 1);
+
+            set_sender(accounts.bob);
+            assert_eq!(contract.set_verbose_events(true), Err(Error::Unauthorized));
+        }
+
+        #[ink::test]
+        fn test_request_quota() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.remaining_quota(accounts.alice), u32::MAX);
+            assert_eq!(contract.set_quota(2, 0), Err(Error::ValueError));
+            assert!(contract.set_quota(0, 0).is_ok());
+            assert!(contract.set_quota(2, 10).is_ok());
+            assert_eq!(contract.remaining_quota(accounts.alice), 2);
+
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert_eq!(contract.remaining_quota(accounts.alice), 0);
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::QuotaExceeded));
+
+            // the quota resets once the window elapses
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(contract.remaining_quota(accounts.alice), 2);
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert_eq!(contract.remaining_quota(accounts.alice), 1);
+
+            set_sender(accounts.bob);
+            assert_eq!(contract.set_quota(0, 0), Err(Error::Unauthorized));
+        }
+
+        #[ink::test]
+        fn test_reward_vesting() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.set_fee(100).is_ok());
+            paid_request(&mut contract, accounts.alice, 100);
+            set_sender(accounts.alice);
+            assert!(contract
+                .simple_callback(1, accounts.alice, OracleResult::Numeric(1))
+                .is_ok());
+            assert_eq!(contract.releasable_rewards(accounts.alice), 100);
+            assert!(contract.set_vesting_blocks(10).is_ok());
+            assert_eq!(contract.releasable_rewards(accounts.alice), 0);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(contract.releasable_rewards(accounts.alice), 50);
+
+            // the contract can't transfer, so the released rewards are owed instead
+            set_balance(contract_id(), 0);
+            assert!(contract.claim_rewards().is_ok());
+            assert_eq!(contract.pending_refund(accounts.alice), 50);
+            assert_eq!(contract.pending_rewards(), 50);
+            assert_eq!(contract.releasable_rewards(accounts.alice), 0);
+
+            // capped at the full amount
+            for _ in 0..20 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(contract.releasable_rewards(accounts.alice), 50);
+
+            set_sender(accounts.bob);
+            assert_eq!(contract.set_vesting_blocks(0), Err(Error::Unauthorized));
+        }
+
+        #[ink::test]
+        fn test_can_refund() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.set_fee(100).is_ok());
+            assert_eq!(paid_request(&mut contract, accounts.alice, 100), 1);
+            assert_eq!(paid_request(&mut contract, accounts.alice, 100), 2);
+            set_sender(accounts.alice);
+            let funded = u128::MAX / 2;
+
+            // before expiry, as long as the contract holds the fee
+            set_balance(contract_id(), funded);
+            assert!(contract.can_refund(1));
+            set_balance(contract_id(), 0);
+            assert!(!contract.can_refund(1));
+            assert!(!contract.can_refund(7));
+
+            // nothing to refund after delivery
+            assert!(contract
+                .simple_callback(2, accounts.alice, OracleResult::Numeric(1))
+                .is_ok());
+            set_balance(contract_id(), funded);
+            assert!(!contract.can_refund(2));
+
+            // after expiry, until the request is cleared
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert!(contract.can_refund(1));
+            set_balance(contract_id(), 0);
+            assert!(!contract.can_refund(1));
+            assert!(contract.clear_expired(1).is_ok());
+            set_balance(contract_id(), funded);
+            assert!(!contract.can_refund(1));
+        }
+
+        #[ink::test]
+        fn test_gas_refund_split() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.set_fee(100).is_ok());
+            assert!(contract.set_treasury(accounts.eve).is_ok());
+            assert_eq!(contract.set_gas_refund_bps(10_001), Err(Error::ValueError));
+            assert!(contract.set_gas_refund_bps(2_500).is_ok());
+
+            // the oracle's share is credited on delivery, the rest held for the treasury
+            paid_request(&mut contract, accounts.alice, 100);
+            set_sender(accounts.alice);
+            assert!(contract
+                .simple_callback(1, accounts.alice, OracleResult::Numeric(1))
+                .is_ok());
+            assert_eq!(contract.pending_rewards(), 25);
+            assert_eq!(contract.treasury_balance, 75);
+
+            // only the admin sends it to the treasury
+            set_sender(accounts.bob);
+            assert_eq!(contract.withdraw_treasury(), Err(Error::Unauthorized));
+            set_sender(accounts.alice);
+            set_balance(contract_id(), 1000);
+            let before = get_balance(accounts.eve);
+            assert!(contract.withdraw_treasury().is_ok());
+            assert_eq!(get_balance(accounts.eve), before + 75);
+            assert_eq!(contract.treasury_balance, 0);
+            assert_eq!(contract.pending_rewards(), 25);
+
+            // nothing left to withdraw
+            assert!(contract.withdraw_treasury().is_ok());
+            assert_eq!(get_balance(accounts.eve), before + 75);
+        }
+
+        #[ink::test]
+        fn test_reward_vesting_per_oracle() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.add_oracle(accounts.alice).is_ok());
+            assert!(contract.add_oracle(accounts.bob).is_ok());
+            assert!(contract.set_fee(100).is_ok());
+            assert!(contract.set_vesting_blocks(10).is_ok());
+
+            // assigned round-robin, alice delivers the first and bob the second request
+            assert_eq!(paid_request(&mut contract, accounts.alice, 100), 1);
+            assert_eq!(paid_request(&mut contract, accounts.alice, 100), 2);
+            set_sender(accounts.alice);
+            assert!(contract
+                .simple_callback(1, accounts.eve, OracleResult::Numeric(1))
+                .is_ok());
+            set_sender(accounts.bob);
+            assert!(contract
+                .simple_callback(2, accounts.eve, OracleResult::Numeric(1))
+                .is_ok());
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+
+            // alice's claim doesn't restart bob's vesting
+            set_balance(contract_id(), 1000);
+            set_sender(accounts.alice);
+            assert_eq!(contract.claim_rewards_to(accounts.django), Ok(()));
+            assert_eq!(contract.releasable_rewards(accounts.alice), 0);
+            assert_eq!(contract.releasable_rewards(accounts.bob), 100);
+
+            // alice's new rewards vest since her own claim
+            assert_eq!(paid_request(&mut contract, accounts.alice, 100), 3);
+            set_sender(accounts.alice);
+            assert!(contract
+                .simple_callback(3, accounts.eve, OracleResult::Numeric(1))
+                .is_ok());
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(contract.releasable_rewards(accounts.alice), 50);
+            assert_eq!(contract.claim_rewards_to(accounts.django), Ok(()));
+            assert_eq!(contract.pending_rewards(), 150);
+
+            // alice's unvested rest isn't split with bob's next delivery
+            assert_eq!(paid_request(&mut contract, accounts.alice, 100), 4);
+            set_sender(accounts.bob);
+            assert!(contract
+                .simple_callback(4, accounts.eve, OracleResult::Numeric(1))
+                .is_ok());
+            assert_eq!(contract.releasable_rewards(accounts.bob), 200);
+            assert_eq!(contract.releasable_rewards(accounts.alice), 0);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+
+            set_balance(contract_id(), 0);
+            set_sender(accounts.alice);
+            assert!(contract.claim_rewards().is_ok());
+            assert_eq!(contract.pending_refund(accounts.alice), 50);
+            assert_eq!(contract.pending_refund(accounts.bob), 200);
+            assert_eq!(contract.pending_rewards(), 0);
+        }
+
+        #[ink::test]
+        fn test_revoke_oracle() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+
+            set_sender(accounts.bob);
+            assert_eq!(contract.revoke_oracle(), Err(Error::Unauthorized));
+            set_sender(accounts.alice);
+            assert!(contract.revoke_oracle().is_ok());
+
+            // pending requests are refunded, nothing can be requested or delivered
+            assert_eq!(contract.health().1, 0);
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::Paused));
+            assert_eq!(
+                contract.simple_callback(1, accounts.bob, OracleResult::Numeric(1)),
+                Err(Error::Unauthorized)
+            );
+
+            // back to business with a new oracle key
+            assert!(contract.set_oracle(accounts.bob).is_ok());
+            assert!(contract.set_paused(false).is_ok());
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(3));
+        }
+
+        #[ink::test]
+        fn test_late_delivery() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            let events = ink_env::test::recorded_events().count();
+            assert_eq!(
+                contract.callback(1, pql_hash, accounts.bob, OracleResult::Numeric(42)),
+                Err(Error::RequestExpired)
+            );
+            // DeliveryTooLate and RequestInvalidated
+            assert_eq!(ink_env::test::recorded_events().count(), events + 2);
+            assert_eq!(contract.health().1, 0);
+        }
+
+        #[ink::test]
+        fn test_callback_batch() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            // an expired and an unknown request both fail without
+            // aborting the batch
+            assert_eq!(
+                contract.callback_batch(vec![
+                    (1, accounts.bob, OracleResult::Numeric(42)),
+                    (7, accounts.bob, OracleResult::Numeric(42)),
+                ]),
+                Ok(vec![false, false])
+            );
+            assert_eq!(contract.health().1, 0);
+
+            set_sender(accounts.bob);
+            assert_eq!(
+                contract.callback_batch(vec![(1, accounts.bob, OracleResult::Numeric(42))]),
+                Err(Error::Unauthorized)
+            );
+        }
+
+        #[ink::test]
+        fn test_callback_with_secret() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            let result = OracleResult::Numeric(42);
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+
+            // the secret deliveries go trough the same checks as `callback`
+            assert_eq!(
+                contract.callback_with_secret(
+                    1,
+                    Hash::from([0x01; 32]),
+                    accounts.bob,
+                    result.clone(),
+                    [0x01; 32],
+                    Hash::default()
+                ),
+                Err(Error::HashMismatch)
+            );
+            set_sender(accounts.bob);
+            assert_eq!(
+                contract.callback_with_secret(
+                    1,
+                    pql_hash,
+                    accounts.bob,
+                    result.clone(),
+                    [0x01; 32],
+                    Hash::default()
+                ),
+                Err(Error::Unauthorized)
+            );
+            set_sender(accounts.alice);
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(
+                contract.callback_with_secret(1, pql_hash, accounts.bob, result, [0x01; 32], Hash::default()),
+                Err(Error::RequestExpired)
+            );
+        }
+
+        #[ink::test]
+        fn test_callback_error_checks() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert_eq!(
+                contract.callback_error(1, Hash::from([0x01; 32]), accounts.bob, 1),
+                Err(Error::HashMismatch)
+            );
+
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(
+                contract.callback_error(1, pql_hash, accounts.bob, 1),
+                Err(Error::RequestExpired)
+            );
+            assert_eq!(contract.health().1, 0);
+            assert_eq!(
+                contract.callback_error(1, pql_hash, accounts.bob, 1),
+                Err(Error::RequestNotFound)
+            );
+        }
+
+        #[ink::test]
+        fn test_request_ids_are_not_reused() {
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            contract.request_idx = u64::MAX;
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::ValueError));
+            assert_eq!(contract.health().1, 0);
+            assert!(!contract.requests.contains_key(&0));
+        }
+
+        #[ink::test]
+        fn test_max_requests() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert!(contract.set_max_requests(2).is_ok());
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert!(contract.request(pql_hash, 20, 0, 0).is_ok());
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::StorageFull));
+
+            // the oldest expired request makes room
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(3));
+            assert!(!contract.requests.contains_key(&1));
+            assert!(contract.requests.contains_key(&2));
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::StorageFull));
+        }
+
+        #[ink::test]
+        fn test_failed_refund_is_owed() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let fee: Balance = (100 as u128).into();
+            assert!(contract.set_fee(fee).is_ok());
+            let pql_hash = sample_ipfs_hash();
+
+            let mut data =
+                ink_env::test::CallData::new(ink_env::call::Selector::new(REQUEST_SELECTOR));
+            data.push_arg(&pql_hash);
+            data.push_arg(&10);
+            data.push_arg(&0u8);
+            data.push_arg(&(0 as Balance));
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.alice,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                fee,
+                data,
+            );
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+
+            // the contract can't pay right now, the refund is queued
+            set_balance(contract_id(), 0);
+            assert!(contract.cancel_request(1).is_ok());
+            assert_eq!(contract.pending_refund(accounts.alice), fee);
+        }
+
+        #[ink::test]
+        fn test_expiry_penalty() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.set_penalty_per_expiry(30).is_ok());
+            assert!(contract.set_fee(50).is_ok());
+            for _ in 0..4 {
+                paid_request(&mut contract, accounts.alice, 50);
+            }
+            set_sender(accounts.alice);
+            assert!(contract
+                .simple_callback(1, accounts.alice, OracleResult::Numeric(1))
+                .is_ok());
+            assert_eq!(contract.pending_rewards(), 50);
+
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert!(contract.clear_expired(2).is_ok());
+            assert_eq!(contract.pending_rewards(), 20);
+            // never more than the oracle earned
+            assert!(contract.clear_expired(3).is_ok());
+            assert!(contract.clear_expired(4).is_ok());
+            assert_eq!(contract.pending_rewards(), 0);
+        }
+
+        #[ink::test]
+        fn test_expiry_penalty_hits_assigned_oracle() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.add_oracle(accounts.bob).is_ok());
+            assert!(contract.add_oracle(accounts.charlie).is_ok());
+            assert!(contract.set_penalty_per_expiry(30).is_ok());
+            assert!(contract.set_fee(50).is_ok());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.bob,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                100,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+            contract.stake();
+
+            // assigned round-robin, charlie delivers and bob doesn't
+            assert_eq!(paid_request(&mut contract, accounts.alice, 50), 1);
+            assert_eq!(paid_request(&mut contract, accounts.alice, 50), 2);
+            set_sender(accounts.charlie);
+            assert!(contract
+                .simple_callback(2, accounts.alice, OracleResult::Numeric(1))
+                .is_ok());
+            assert_eq!(contract.pending_rewards(), 50);
+
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert!(contract.clear_expired(1).is_ok());
+            assert_eq!(contract.stake_of(accounts.bob), 70);
+            // the rewards of the other oracles are left alone
+            assert_eq!(contract.pending_rewards(), 50);
+        }
+
+        #[ink::test]
+        fn test_staking() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.set_min_stake(100).is_ok());
+
+            // bob needs to stake first
+            assert_eq!(contract.set_oracle(accounts.bob), Err(Error::InsufficientFunds));
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.bob,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                100,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+            contract.stake();
+            assert_eq!(contract.stake_of(accounts.bob), 100);
+            set_sender(accounts.alice);
+            assert!(contract.set_oracle(accounts.bob).is_ok());
+
+            // penalties slash the stake first
+            assert!(contract.set_penalty_per_expiry(30).is_ok());
+            assert!(contract.request(sample_ipfs_hash(), 10, 0, 0).is_ok());
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert!(contract.clear_expired(1).is_ok());
+            assert_eq!(contract.stake_of(accounts.bob), 70);
+
+            // no unstaking with outstanding requests
+            assert!(contract.request(sample_ipfs_hash(), 10, 0, 0).is_ok());
+            set_sender(accounts.bob);
+            assert_eq!(contract.unstake(), Err(Error::RequestsOutstanding));
+        }
+
+        #[ink::test]
+        fn test_pool_oracle_unstake() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.add_oracle(accounts.bob).is_ok());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.bob,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                100,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+            contract.stake();
+            set_sender(accounts.alice);
+            assert_eq!(contract.request(sample_ipfs_hash(), 10, 0, 0), Ok(1));
+
+            // no unstaking with an undelivered request assigned
+            set_sender(accounts.bob);
+            assert_eq!(contract.unstake(), Err(Error::RequestsOutstanding));
+            assert!(contract
+                .simple_callback(1, accounts.alice, OracleResult::Numeric(1))
+                .is_ok());
+            assert_eq!(contract.unstake(), Ok(100));
+            assert_eq!(contract.stake_of(accounts.bob), 0);
+        }
+
+        #[ink::test]
+        fn test_default_valid_period() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request_default(pql_hash), Ok(1));
+            assert_eq!(contract.requests.get(&1).unwrap().1, 100);
+
+            assert_eq!(contract.set_default_valid_period(101), Err(Error::ValueError));
+            assert_eq!(contract.set_default_valid_period(20), Ok(()));
+            // the bounds have to keep including the default
+            assert_eq!(contract.set_valid_period_bounds(30, 50), Err(Error::ValueError));
+            assert_eq!(contract.set_valid_period_bounds(20, 10), Err(Error::ValueError));
+            assert_eq!(contract.set_valid_period_bounds(10, 20), Ok(()));
+            assert_eq!(contract.request(pql_hash, 21, 0, 0), Err(Error::ValueError));
+
+            set_sender(accounts.bob);
+            assert_eq!(contract.set_default_valid_period(10), Err(Error::Unauthorized));
+            assert_eq!(contract.set_valid_period_bounds(1, 200), Err(Error::Unauthorized));
+        }
+
+        #[ink::test]
+        fn test_result_kind() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request_typed(pql_hash, 10, 0, 0, 3), Err(Error::ValueError));
+            assert_eq!(contract.request_typed(pql_hash, 10, 0, 0, RESULT_KIND_NUMERIC), Ok(1));
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(2));
+
+            assert_eq!(
+                contract.simple_callback(1, accounts.bob, OracleResult::RawBytes([0x0; 32])),
+                Err(Error::ResultTypeMismatch)
+            );
+            assert!(contract.simple_callback(1, accounts.bob, OracleResult::Numeric(1)).is_ok());
+            // any kind by default
+            assert!(contract.simple_callback(2, accounts.bob, OracleResult::Boolean(true)).is_ok());
+        }
+
+        #[ink::test]
+        fn test_refunds() {
+            // alice is admin
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+
+            // admin sets the fee
+            let mut contract = TrustedOracle::default();
+            let fee: Balance = (100 as u128).into();
+            assert!(contract.set_fee(fee).is_ok());
+            assert_eq!(contract.get_fee(), fee);
+
+            // request is made and paid for
+            let pql_hash = sample_ipfs_hash();
+            set_sender(accounts.alice);
+            set_balance(accounts.alice, fee);
+            // TODO: testing if transfer occured is not yet possible
+            // in the current version of Ink. Uncomment the get_balance
+            // assertions when the ink::test env is fixed.
+            // assert_eq!(get_balance(accounts.alice), fee);
+            let mut data =
+                ink_env::test::CallData::new(ink_env::call::Selector::new(REQUEST_SELECTOR));
+            data.push_arg(&pql_hash);
+            data.push_arg(&10);
+            data.push_arg(&0u8);
+            data.push_arg(&(0 as Balance));
+
+            // Send "fee" value into the contract
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.alice,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                fee,
+                data,
+            );
+            // assert_eq!(get_balance(accounts.alice), fee);
+            // assert_eq!(get_balance(contract_id()), 0);
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            // assert_eq!(get_balance(contract_id()), fee);
+            // assert_eq!(get_balance(accounts.alice), 0);
+
+            // request expires due to non-response
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+
+            // request is refunded
+            // assert!(contract.clear_expired(1).is_ok());
+            // assert_eq!(get_balance(contract_id()), 0);
+            // assert_eq!(get_balance(accounts.alice), fee);
+        }
+
+        #[ink::test]
+        fn test_fee_change_keeps_refund() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let fee: Balance = (100 as u128).into();
+            assert!(contract.set_fee(fee).is_ok());
+
+            let pql_hash = sample_ipfs_hash();
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.alice,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                fee,
+                ink_env::test::CallData::new(ink_env::call::Selector::new(REQUEST_SELECTOR)),
+            );
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+
+            // the fee is lowered while the request is pending
+            set_sender(accounts.alice);
+            assert!(contract.set_fee(0).is_ok());
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+
+            // the contract can't transfer, so the refund is owed instead
+            set_balance(contract_id(), 0);
+            assert!(contract.clear_expired(1).is_ok());
+            assert_eq!(contract.pending_refund(accounts.alice), fee);
+        }
+
+        #[ink::test]
+        fn test_rejected_delivery_refunds_fee() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let fee: Balance = (100 as u128).into();
+            assert!(contract.set_fee(fee).is_ok());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.alice,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                fee,
+                ink_env::test::CallData::new(ink_env::call::Selector::new(REQUEST_SELECTOR)),
+            );
+            assert_eq!(contract.request(sample_ipfs_hash(), 10, 0, 0), Ok(1));
+
+            // the consumer rejects the result, the fee is refunded
+            // instead of paid to the oracle. Consumers can't be called
+            // off-chain, so the rejection is simulated.
+            set_balance(contract_id(), 0);
+            assert!(contract.reject_delivery_(1).is_ok());
+            assert_eq!(contract.pending_refund(accounts.alice), fee);
+            assert_eq!(contract.pending_rewards(), 0);
+
+            // the request can be retried, unpaid
+            assert_eq!(contract.requests.get(&1).unwrap().2, 0);
+            set_sender(accounts.alice);
+            assert!(contract
+                .simple_callback(1, accounts.alice, OracleResult::Numeric(1))
+                .is_ok());
+            assert_eq!(contract.pending_rewards(), 0);
+        }
+
+        #[ink::test]
+        fn test_fallback_to_poll() {
             let accounts = default_accounts();
             set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request_with_callback(pql_hash, 10, 0, 0, accounts.bob), Ok(1));
+            let result = OracleResult::Numeric(42);
 
-            // default contract
-            let contract = TrustedOracle::default();
-            assert!(contract.admin == accounts.alice);
-            assert!(contract.authorized_oracle == accounts.alice);
-            assert!(contract.authorized_users.contains_key(&accounts.alice));
+            // consumers can't be called off-chain, so the failed deliveries
+            // are simulated. Never falls back by default
+            assert!(!contract.fall_back_to_poll_(1, accounts.alice, &result));
+            assert!(contract.set_max_delivery_attempts(3).is_ok());
+            assert!(!contract.fall_back_to_poll_(1, accounts.alice, &result));
+            assert!(contract.fall_back_to_poll_(1, accounts.alice, &result));
+            assert_eq!(contract.deliveries_by(accounts.alice), 0);
+
+            // the result can't be lost to an expiry
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(contract.clear_expired(1), Err(Error::RequestNotExpired));
+
+            // only the consumer can collect it
+            assert_eq!(contract.collect_result(1), Err(Error::Unauthorized));
+            set_sender(accounts.bob);
+            assert_eq!(contract.collect_result(1), Ok(result));
+            assert!(contract.was_delivered(1));
+            assert_eq!(contract.deliveries_by(accounts.alice), 1);
+            assert_eq!(contract.collect_result(1), Err(Error::RequestNotFound));
         }
 
         #[ink::test]
-        fn test_make_free_request() {
+        fn test_stored_result_is_kept() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
             let mut contract = TrustedOracle::default();
             let pql_hash = sample_ipfs_hash();
-            contract.request(pql_hash, 10);
+            assert_eq!(contract.request_with_callback(pql_hash, 10, 0, 0, accounts.bob), Ok(1));
+            let result = OracleResult::Numeric(42);
+            // simulate the failed delivery, consumers can't be called off-chain
+            assert!(contract.set_max_delivery_attempts(1).is_ok());
+            assert!(contract.fall_back_to_poll_(1, accounts.alice, &result));
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+
+            // a late delivery doesn't drop it
+            assert_eq!(
+                contract.callback(1, pql_hash, accounts.bob, result.clone()),
+                Err(Error::ResultPending)
+            );
+            assert_eq!(
+                contract.callback_error(1, pql_hash, accounts.bob, 1),
+                Err(Error::ResultPending)
+            );
+            assert_eq!(contract.cancel_request(1), Err(Error::ResultPending));
+
+            // it doesn't make room for new requests
+            assert!(contract.set_max_requests(1).is_ok());
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::StorageFull));
+
+            // and isn't refunded
+            assert_eq!(contract.refund_pending(10), Ok(0));
+
+            set_sender(accounts.bob);
+            assert_eq!(contract.collect_result(1), Ok(result));
         }
 
         #[ink::test]
-        fn test_make_paid_request() {
-            // alice is admin
+        fn test_stored_result_is_released() {
             let accounts = default_accounts();
             set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.set_fee(100).is_ok());
+            assert!(contract.set_max_delivery_attempts(1).is_ok());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                accounts.bob,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                150,
+                ink_env::test::CallData::new(ink_env::call::Selector::new(REQUEST_SELECTOR)),
+            );
+            assert_eq!(contract.request(sample_ipfs_hash(), 10, 0, 50), Ok(1));
+            // simulate the failed delivery, consumers can't be called off-chain
+            assert!(contract.fall_back_to_poll_(1, accounts.alice, &OracleResult::Numeric(42)));
 
-            // admin sets the fee
+            // kept for `max_valid_period` blocks after the request expired
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(contract.clear_expired(1), Err(Error::RequestNotExpired));
+            for _ in 0..100 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+
+            // then the oracle is paid and the callback value returned
+            set_balance(contract_id(), 0);
+            assert!(contract.clear_expired(1).is_ok());
+            assert_eq!(contract.deliveries_by(accounts.alice), 1);
+            assert_eq!(contract.pending_rewards(), 100);
+            assert_eq!(contract.pending_refund(accounts.bob), 50);
+            set_sender(accounts.bob);
+            assert_eq!(contract.collect_result(1), Err(Error::RequestNotFound));
+        }
+
+        #[ink::test]
+        fn test_requests_by_priority() {
             let mut contract = TrustedOracle::default();
-            let fee: Balance = (100 as u128).into();
-            assert!(contract.set_fee(fee).is_ok());
-            assert!(contract.fee == fee);
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request(pql_hash, 20, 0, 0), Ok(1));
+            assert_eq!(contract.request(pql_hash, 20, 5, 0), Ok(2));
+            assert_eq!(contract.request(pql_hash, 10, 9, 0), Ok(3));
+            assert_eq!(contract.list_requests_by_priority(5, 10), vec![3, 2]);
+            assert_eq!(contract.list_requests_by_priority(0, 1), vec![3]);
+
+            // expired requests are skipped, however high their priority
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(contract.request(pql_hash, 10, 7, 0), Ok(4));
+            assert_eq!(contract.list_requests_by_priority(5, 10), vec![4, 2]);
+            assert_eq!(contract.list_requests_by_priority(0, 10), vec![4, 2, 1]);
+        }
 
+        #[ink::test]
+        fn test_list_pending() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
             let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+            assert_eq!(contract.request(pql_hash, 20, 0, 0), Ok(2));
+            assert_eq!(contract.request(pql_hash, 20, 0, 0), Ok(3));
 
-            // payment required
-            assert_eq!(contract.request(pql_hash, 10), Err(Error::PaymentRequired));
+            let pending = contract.list_pending(0, 2);
+            assert_eq!(pending.len(), 2);
+            assert_eq!(pending[0], (1, accounts.alice, pql_hash, 10, 0));
+            assert_eq!(pending[1].0, 2);
+            assert_eq!(contract.list_pending(3, 10).len(), 1);
 
-            // kinda hacky way of sending value into contract
-            // assert!(contract.request(pql_hash, 10, {value: 10}).is_ok());
+            // expired requests are skipped
+            for _ in 0..11 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            let ids: Vec<u64> = contract.list_pending(0, 10).iter().map(|r| r.0).collect();
+            assert_eq!(ids, vec![2, 3]);
+        }
+
+        #[ink::test]
+        fn test_claim_rewards_to() {
+            let accounts = default_accounts();
             set_sender(accounts.alice);
-            set_balance(accounts.alice, fee);
-            let mut data = ink_env::test::CallData::new(ink_env::call::Selector::new([
-                0xB1, 0x6B, 0x00, 0xB5,
-            ]));
-            data.push_arg(&pql_hash);
-            data.push_arg(&10);
 
-            // Send "fee" value into the contract
-            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
-                accounts.alice,
-                contract_id(),
-                DEFAULT_GAS_LIMIT,
-                fee,
-                data,
+            let mut contract = TrustedOracle::default();
+            assert_eq!(contract.pending_rewards(), 0);
+            assert_eq!(
+                contract.claim_rewards_to(AccountId::from([0x0; 32])),
+                Err(Error::ValueError)
+            );
+            assert_eq!(contract.claim_rewards_to(accounts.bob), Ok(()));
+
+            // only the oracle can claim
+            set_sender(accounts.bob);
+            assert_eq!(
+                contract.claim_rewards_to(accounts.bob),
+                Err(Error::Unauthorized)
             );
-            assert!(contract.request(pql_hash, 10).is_ok());
         }
 
         #[ink::test]
-        fn test_refunds() {
-            // alice is admin
+        fn test_claim_rewards_to_keeps_other_shares() {
             let accounts = default_accounts();
             set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.add_oracle(accounts.alice).is_ok());
+            assert!(contract.add_oracle(accounts.bob).is_ok());
+            assert!(contract.set_fee(30).is_ok());
+            for _ in 0..4 {
+                paid_request(&mut contract, accounts.alice, 30);
+            }
+            // assigned round-robin, alice delivers one and bob two requests
+            set_sender(accounts.alice);
+            assert!(contract
+                .simple_callback(1, accounts.eve, OracleResult::Numeric(1))
+                .is_ok());
+            set_sender(accounts.bob);
+            assert!(contract
+                .simple_callback(2, accounts.eve, OracleResult::Numeric(1))
+                .is_ok());
+            assert!(contract
+                .simple_callback(4, accounts.eve, OracleResult::Numeric(1))
+                .is_ok());
 
-            // admin sets the fee
+            // only alice's share is paid out
+            set_balance(contract_id(), 1000);
+            set_sender(accounts.alice);
+            assert_eq!(contract.claim_rewards_to(accounts.django), Ok(()));
+            assert_eq!(contract.pending_rewards(), 60);
+            assert_eq!(contract.releasable_rewards(accounts.alice), 0);
+            assert_eq!(contract.releasable_rewards(accounts.bob), 60);
+
+            // bob's share is kept for the next claim
+            set_balance(contract_id(), 0);
+            assert!(contract.claim_rewards().is_ok());
+            assert_eq!(contract.pending_refund(accounts.bob), 60);
+            assert_eq!(contract.pending_rewards(), 0);
+        }
+
+        #[ink::test]
+        fn test_pql_hash_validation() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
             let mut contract = TrustedOracle::default();
-            let fee: Balance = (100 as u128).into();
-            assert!(contract.set_fee(fee).is_ok());
-            assert!(contract.fee == fee);
+            let zero_hash = Hash::from([0x0; 32]);
+            assert_eq!(contract.request(zero_hash, 10, 0, 0), Err(Error::InvalidPqlHash));
+            assert_eq!(
+                contract.request_on_behalf(accounts.alice, zero_hash, 10),
+                Err(Error::InvalidPqlHash)
+            );
 
-            // request is made and paid for
+            // only registered definitions
+            let mut contract =
+                TrustedOracle::new(accounts.alice, accounts.alice, 10, 100, false, true);
+            assert!(contract.add_user(accounts.alice).is_ok());
             let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::InvalidPqlHash));
+            assert!(contract.allow_pql_hash(pql_hash).is_ok());
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+            assert_eq!(contract.request(zero_hash, 10, 0, 0), Err(Error::InvalidPqlHash));
+        }
+
+        #[ink::test]
+        fn test_callback_returns_result() {
+            let accounts = default_accounts();
             set_sender(accounts.alice);
-            set_balance(accounts.alice, fee);
-            // TODO: testing if transfer occured is not yet possible
-            // in the current version of Ink. Uncomment the get_balance
-            // assertions when the ink::test env is fixed.
-            // assert_eq!(get_balance(accounts.alice), fee);
-            let mut data = ink_env::test::CallData::new(ink_env::call::Selector::new([
-                0xB1, 0x6B, 0x00, 0xB5,
-            ]));
-            data.push_arg(&pql_hash);
-            data.push_arg(&10);
+            let mut contract = TrustedOracle::default();
+            assert!(contract.callback_returns_result());
+            assert!(contract.set_callback_returns_result(false).is_ok());
+            assert!(!contract.callback_returns_result());
+            assert!(contract.set_callback_returns_result(true).is_ok());
+            assert!(contract.callback_returns_result());
 
-            // Send "fee" value into the contract
-            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
-                accounts.alice,
-                contract_id(),
-                DEFAULT_GAS_LIMIT,
-                fee,
-                data,
+            let strict = TrustedOracle::new(accounts.alice, accounts.alice, 10, 100, false, false);
+            assert!(strict.callback_returns_result());
+
+            set_sender(accounts.bob);
+            assert_eq!(contract.set_callback_returns_result(false), Err(Error::Unauthorized));
+        }
+
+        #[ink::test]
+        fn test_supported_result_kinds() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            assert_eq!(
+                contract.supported_result_kinds(),
+                vec![RESULT_KIND_NUMERIC, RESULT_KIND_RAW_BYTES]
             );
-            // assert_eq!(get_balance(accounts.alice), fee);
-            // assert_eq!(get_balance(contract_id()), 0);
-            assert!(contract.request(pql_hash, 10).is_ok());
-            // assert_eq!(get_balance(contract_id()), fee);
-            // assert_eq!(get_balance(accounts.alice), 0);
+            assert!(contract.set_supported_kinds(vec![RESULT_KIND_NUMERIC]).is_ok());
+            assert_eq!(contract.supported_result_kinds(), vec![RESULT_KIND_NUMERIC]);
 
-            // request expires due to non-response
-            for _ in 0..10 {
+            set_sender(accounts.bob);
+            assert_eq!(contract.set_supported_kinds(vec![]), Err(Error::Unauthorized));
+        }
+
+        #[ink::test]
+        fn test_annotations() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request_annotated(pql_hash, 10, 0, 0, [0x01; 32]), Ok(1));
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(2));
+            assert_eq!(contract.annotation_of(1), Some([0x01; 32]));
+            assert_eq!(contract.annotation_of(2), None);
+
+            // cleared with the request
+            assert!(contract
+                .simple_callback(1, accounts.alice, OracleResult::Numeric(1))
+                .is_ok());
+            assert_eq!(contract.annotation_of(1), None);
+        }
+
+        #[ink::test]
+        fn test_pql_labels() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract =
+                TrustedOracle::new(accounts.alice, accounts.alice, 10, 100, false, true);
+            assert!(contract.add_user(accounts.alice).is_ok());
+            let pql_hash = sample_ipfs_hash();
+            let mut label = [0x0; 32];
+            label[..7].copy_from_slice(b"btc-usd");
+            assert_eq!(contract.label_of(pql_hash), None);
+
+            assert!(contract.register_pql(pql_hash, label).is_ok());
+            assert_eq!(contract.label_of(pql_hash), Some(label));
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+
+            assert!(contract.disallow_pql_hash(pql_hash).is_ok());
+            assert_eq!(contract.label_of(pql_hash), None);
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::InvalidPqlHash));
+
+            set_sender(accounts.bob);
+            assert_eq!(contract.register_pql(pql_hash, label), Err(Error::Unauthorized));
+        }
+
+        #[ink::test]
+        fn test_dedup_requests() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+
+            let mut contract =
+                TrustedOracle::new(accounts.alice, accounts.alice, 10, 100, true, false);
+            assert!(contract.add_user(accounts.alice).is_ok());
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+            assert_eq!(contract.subscribers.get(&1).map(|s| s.len()), Some(1));
+
+            // strict mode creates a request every time
+            let mut contract = TrustedOracle::default();
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(2));
+        }
+
+        #[ink::test]
+        fn test_dedup_fan_out() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract =
+                TrustedOracle::new(accounts.alice, accounts.alice, 10, 100, true, false);
+            assert!(contract.set_fee(100).is_ok());
+            for user in [accounts.alice, accounts.bob, accounts.charlie].iter() {
+                assert!(contract.add_user(*user).is_ok());
+            }
+            let pql_hash = sample_ipfs_hash();
+            let request = |contract: &mut TrustedOracle, from: AccountId, callback_value: Balance| {
+                ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                    from,
+                    contract_id(),
+                    DEFAULT_GAS_LIMIT,
+                    100 + callback_value,
+                    ink_env::test::CallData::new(ink_env::call::Selector::new(REQUEST_SELECTOR)),
+                );
+                contract.request(pql_hash, 10, 0, callback_value)
+            };
+
+            // bob and charlie subscribe to alice's in-flight request
+            assert_eq!(request(&mut contract, accounts.alice, 0), Ok(1));
+            assert_eq!(request(&mut contract, accounts.bob, 10), Ok(1));
+            assert_eq!(request(&mut contract, accounts.charlie, 20), Ok(1));
+            assert_eq!(contract.subscribers.get(&1).map(|s| s.len()), Some(2));
+
+            // every subscriber is delivered the result and pays for it.
+            // Consumers can't be called off-chain, so the result is stored
+            // for them to read and the callback values are owed back.
+            set_balance(contract_id(), 0);
+            set_sender(accounts.alice);
+            assert!(contract
+                .simple_callback(1, accounts.alice, OracleResult::Numeric(42))
+                .is_ok());
+            assert!(contract.was_delivered(1));
+            assert_eq!(contract.oracle_results(1), 42);
+            assert_eq!(contract.pending_rewards(), 300);
+            assert_eq!(contract.pending_refund(accounts.bob), 10);
+            assert_eq!(contract.pending_refund(accounts.charlie), 20);
+            assert!(contract.subscribers.get(&1).is_none());
+
+            // the subscribers of an expired request are refunded in full
+            assert_eq!(request(&mut contract, accounts.alice, 0), Ok(2));
+            assert_eq!(request(&mut contract, accounts.bob, 10), Ok(2));
+            assert_eq!(request(&mut contract, accounts.charlie, 20), Ok(2));
+            for _ in 0..11 {
                 ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
             }
+            set_balance(contract_id(), 0);
+            set_sender(accounts.alice);
+            assert!(contract.clear_expired(2).is_ok());
+            assert_eq!(contract.pending_refund(accounts.bob), 10 + 110);
+            assert_eq!(contract.pending_refund(accounts.charlie), 20 + 120);
+            assert!(contract.subscribers.get(&2).is_none());
+        }
 
-            // request is refunded
-            // assert!(contract.clear_expired(1).is_ok());
-            // assert_eq!(get_balance(contract_id()), 0);
-            // assert_eq!(get_balance(accounts.alice), fee);
+        #[ink::test]
+        fn test_was_delivered() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+            assert!(!contract.was_delivered(1));
+
+            assert!(contract
+                .simple_callback(1, accounts.bob, OracleResult::Numeric(42))
+                .is_ok());
+            assert!(contract.was_delivered(1));
+            assert_eq!(contract.oracle_results(1), 42);
+
+            // made up ids are neither delivered nor counted
+            assert_eq!(
+                contract.simple_callback(7, accounts.bob, OracleResult::Numeric(42)),
+                Err(Error::RequestNotFound)
+            );
+            assert!(!contract.was_delivered(7));
+            assert_eq!(contract.deliveries_by(accounts.alice), 1);
+        }
+
+        #[ink::test]
+        fn test_result_encoding() {
+            // consumers decode results with their own copy of `OracleResult`
+            assert_eq!(
+                OracleResult::Numeric(1).encode(),
+                vec![0, 1, 0, 0, 0, 0, 0, 0, 0]
+            );
+            assert_eq!(OracleResult::RawBytes([0x42; 32]).encode()[0], 1);
+            assert_eq!(OracleResult::Boolean(true).encode(), vec![2, 1]);
+            assert_eq!(OracleResult::Enum(7).encode(), vec![3, 7]);
+            assert_eq!(OracleResult::Error(1).encode(), vec![4, 1]);
+
+            let result = OracleResult::Enum(7);
+            assert_eq!(OracleResult::decode(&mut &result.encode()[..]).ok(), Some(result));
+        }
+
+        #[ink::test]
+        fn test_request_cooldown() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+
+            // no cooldown by default
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+
+            assert!(contract.set_cooldown_blocks(2).is_ok());
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::Cooldown));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::Cooldown));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            assert!(contract.request(pql_hash, 10, 0, 0).is_ok());
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Err(Error::Cooldown));
+        }
+
+        #[ink::test]
+        fn test_validate_request() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+
+            let mut contract = TrustedOracle::default();
+            assert_eq!(contract.validate_request(10, 0, 0), Ok(()));
+            assert_eq!(contract.validate_request(5, 0, 0), Err(Error::ValueError));
+            assert_eq!(contract.validate_request(101, 0, 0), Err(Error::ValueError));
+            assert_eq!(
+                contract.request(sample_ipfs_hash(), 5, 0, 0),
+                Err(Error::ValueError)
+            );
+
+            let fee: Balance = (100 as u128).into();
+            assert!(contract.set_fee(fee).is_ok());
+            assert_eq!(contract.validate_request(10, 0, 0), Err(Error::PaymentRequired));
+            assert_eq!(contract.validate_request(10, 0, fee), Ok(()));
+            assert_eq!(contract.validate_request(10, 5, fee + 5), Ok(()));
+
+            // bob is not an authorized user
+            set_sender(accounts.bob);
+            assert_eq!(contract.validate_request(10, 0, fee), Err(Error::Unauthorized));
+        }
+
+        #[ink::test]
+        fn test_callback_hash_mismatch() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+
+            // oracle delivers a result for another job
+            let other_hash = Hash::from([0x42; 32]);
+            assert_eq!(
+                contract.callback(1, other_hash, accounts.bob, OracleResult::Numeric(42)),
+                Err(Error::HashMismatch)
+            );
+            assert!(contract.requests.contains_key(&1));
+        }
+
+        #[ink::test]
+        fn test_extend_request() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+            assert_eq!(contract.extend_request(2, 10), Err(Error::RequestNotFound));
+
+            assert_eq!(contract.extend_request(1, 10), Ok(()));
+            assert_eq!(contract.requests.get(&1).map(|r| r.1), Some(20));
+
+            // capped at twice the max_valid_period
+            assert_eq!(contract.extend_request(1, 1000), Ok(()));
+            assert_eq!(contract.requests.get(&1).map(|r| r.1), Some(200));
+
+            // only the admin or the oracle
+            set_sender(accounts.bob);
+            assert_eq!(contract.extend_request(1, 10), Err(Error::Unauthorized));
+        }
+
+        #[test]
+        fn test_selectors() {
+            // decoded the way the contract dispatches the calls, so these
+            // fail if the `selector` attributes drift from the constants
+            let hash = sample_ipfs_hash();
+            assert!(dispatches(REQUEST_SELECTOR, (hash, 10u32, 0u8, 0 as Balance)));
+            assert!(dispatches(
+                REQUEST_WITH_TIP_SELECTOR,
+                (hash, 10u32, 0u8, 0 as Balance, 0 as Balance)
+            ));
+            assert!(dispatches(CANCEL_SELECTOR, 1u64));
+            assert!(dispatches(WITHDRAW_REFUND_SELECTOR, ()));
+            assert!(dispatches(FEE_SELECTOR, ()));
+            assert!(!dispatches([0x00; 4], ()));
+        }
+
+        #[ink::test]
+        fn test_self_callback_rejected() {
+            // alice is admin and oracle
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+
+            // results can't be delivered back into the oracle, or nowhere
+            assert_eq!(
+                contract.request_with_callback(pql_hash, 10, 0, 0, contract_id()),
+                Err(Error::ValueError)
+            );
+            assert_eq!(
+                contract.request_with_callback(pql_hash, 10, 0, 0, AccountId::from([0x0; 32])),
+                Err(Error::ValueError)
+            );
+            assert!(!contract.requests.contains_key(&1));
+        }
+
+        #[ink::test]
+        fn test_bound_callback_addr() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut contract = TrustedOracle::default();
+            let pql_hash = sample_ipfs_hash();
+            assert_eq!(contract.request(pql_hash, 10, 0, 0), Ok(1));
+            assert_eq!(contract.request_with_callback(pql_hash, 10, 0, 0, accounts.bob), Ok(2));
+            assert_eq!(contract.callback_addr_(1, accounts.alice), accounts.alice);
+            assert_eq!(contract.callback_addr_(2, accounts.alice), accounts.bob);
+
+            // the oracle supplied address is ignored
+            assert!(contract
+                .simple_callback(2, accounts.eve, OracleResult::Numeric(1))
+                .is_ok());
+            assert!(!contract.callback_addrs.contains_key(&2));
         }
 
         //
@@ -625,6 +4287,30 @@ mod trusted_oracle {
             )
         }
 
+        /// Whether the contract has a message with the `selector` taking the `args`
+        fn dispatches(selector: [u8; 4], args: impl scale::Encode) -> bool {
+            let mut input = selector.to_vec();
+            args.encode_to(&mut input);
+            <<TrustedOracle as ::ink_lang::MessageDispatcher>::Type as scale::Decode>::decode(
+                &mut &input[..],
+            )
+            .is_ok()
+        }
+
+        /// Make a request as `from`, paying `fee`
+        fn paid_request(contract: &mut TrustedOracle, from: AccountId, fee: Balance) -> u64 {
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                from,
+                contract_id(),
+                DEFAULT_GAS_LIMIT,
+                fee,
+                ink_env::test::CallData::new(ink_env::call::Selector::new(REQUEST_SELECTOR)),
+            );
+            contract
+                .request(sample_ipfs_hash(), 10, 0, 0)
+                .expect("Cannot make request")
+        }
+
         fn set_balance(account_id: AccountId, balance: Balance) {
             ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(account_id, balance)
                 .expect("Cannot set account balance");